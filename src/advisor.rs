@@ -0,0 +1,177 @@
+//! Suggests which laser to fire next.
+//!
+//! Every grid that is still consistent with the observations could be the hidden one. A laser
+//! splits these candidates into groups by what it would show. The best laser leaves as few
+//! candidates as possible, either on average or in the worst case.
+
+use crate::atom_grid::AtomGrid;
+use crate::laser::{Direction, LaserTip, TraversalResult};
+use crate::observation::{Observations, NOT_PROBED};
+
+/// What a good laser should achieve.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Learn as much as possible on average.
+    MaxInformation,
+    /// Keep the number of candidates left in the worst case small.
+    MinWorstCase,
+}
+
+/// A suggested laser together with what firing it is expected to tell.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Advice {
+    pub shift: u8,
+    pub direction: Direction,
+    /// Expected information in bits, assuming all candidates are equally likely.
+    pub information: f64,
+    /// Expected number of candidates left after firing.
+    pub expected_candidates: f64,
+    /// Number of candidates left if the laser shows the least helpful result.
+    pub worst_case_candidates: usize,
+}
+
+/// Rates all lasers which were not fired yet against the candidate grids and returns the best one.
+/// Returns `None` if every laser was fired already. The candidates should be the grids consistent
+/// with the observations, e.g. from [find_solutions](crate::brute_force::find_solutions).
+pub fn best_probe(
+    observations: &Observations,
+    candidates: &[AtomGrid],
+    strategy: Strategy,
+) -> Option<Advice> {
+    let mut best: Option<Advice> = None;
+    for (direction, shift, obs) in observations.iter() {
+        if obs != NOT_PROBED {
+            continue;
+        }
+        let advice = rate_probe(observations, candidates, shift, direction);
+        let better = match best {
+            None => true,
+            Some(best) => match strategy {
+                Strategy::MaxInformation => {
+                    (advice.information, -(advice.worst_case_candidates as f64))
+                        > (best.information, -(best.worst_case_candidates as f64))
+                }
+                Strategy::MinWorstCase => {
+                    (advice.worst_case_candidates, -advice.information)
+                        < (best.worst_case_candidates, -best.information)
+                }
+            },
+        };
+        if better {
+            best = Some(advice);
+        }
+    }
+    best
+}
+
+/// Predicts what firing the given laser tells about the candidates.
+pub fn rate_probe(
+    observations: &Observations,
+    candidates: &[AtomGrid],
+    shift: u8,
+    direction: Direction,
+) -> Advice {
+    let laser = LaserTip::new(shift, direction, observations.size());
+    // Candidates showing the same result stay together.
+    let mut groups: Vec<(TraversalResult, usize)> = vec![];
+    for grid in candidates {
+        let result = observations.traverse(laser, grid);
+        match groups
+            .iter_mut()
+            .find(|(other, _)| other.looks_like(result))
+        {
+            Some((_, count)) => *count += 1,
+            None => groups.push((result, 1)),
+        }
+    }
+
+    let total = candidates.len() as f64;
+    let mut information = 0.0;
+    let mut expected_candidates = 0.0;
+    for &(_, count) in &groups {
+        let p = count as f64 / total;
+        information -= p * p.log2();
+        expected_candidates += p * count as f64;
+    }
+    Advice {
+        shift,
+        direction,
+        information,
+        expected_candidates,
+        worst_case_candidates: groups.iter().map(|&(_, count)| count).max().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::GridSize;
+    use crate::brute_force;
+    use crate::generator;
+    use crate::i8vec2::I8Vec2;
+    use crate::laser::Direction::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// All grids of the given size with exactly one atom.
+    fn single_atoms(size: GridSize) -> Vec<AtomGrid> {
+        size.cells()
+            .map(|v| {
+                let mut grid = AtomGrid::new(size);
+                grid.set(v, true);
+                grid
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_probe() {
+        let size = GridSize::new(3, 3);
+        let observations = Observations::new(size);
+        let candidates = single_atoms(size);
+
+        // Through the middle row, the atom absorbs in the middle row, reflects at the border of
+        // the neighbouring rows and deflects otherwise.
+        let advice = rate_probe(&observations, &candidates, 1, Right);
+        assert_eq!(advice.worst_case_candidates, 3);
+        assert!(advice.information > 1.0);
+        assert!(advice.expected_candidates < 9.0);
+
+        let mut grid = AtomGrid::new(size);
+        grid.set(I8Vec2::new(1, 1), true);
+        assert_eq!(
+            rate_probe(&observations, &[grid.clone(), grid], 0, Down).information,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_best_probe() {
+        let size = GridSize::new(3, 3);
+        let observations = Observations::new(size);
+        let candidates = single_atoms(size);
+        for strategy in [Strategy::MaxInformation, Strategy::MinWorstCase] {
+            let advice = best_probe(&observations, &candidates, strategy).unwrap();
+            // Every middle laser splits the candidates into at most three.
+            assert_eq!(advice.shift, 1);
+            assert_eq!(advice.worst_case_candidates, 3);
+        }
+
+        // Following the advice finds the hidden grid.
+        let size = GridSize::new(5, 5);
+        let hidden = generator::unique_puzzle(size, 3, &mut StdRng::seed_from_u64(14)).unwrap();
+        let mut observations = Observations::new(size);
+        let mut candidates = vec![];
+        for _ in 0..observations.iter().len() {
+            candidates = brute_force::find_all_solutions(&observations, 3);
+            if candidates.len() == 1 {
+                break;
+            }
+            let advice = best_probe(&observations, &candidates, Strategy::MaxInformation)
+                .expect("Unfired lasers left");
+            let laser = LaserTip::new(advice.shift, advice.direction, size);
+            observations.probe(laser, &hidden);
+        }
+        assert_eq!(candidates, [hidden]);
+    }
+}
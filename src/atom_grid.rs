@@ -1,32 +1,312 @@
+//! The hidden grid of atoms and other objects.
+
 use crate::i8vec2::I8Vec2;
+use crate::laser::Direction;
+use crate::observation;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The dimensions of the classic 8x8 black box.
+pub const DEFAULT_SIZE: GridSize = GridSize::square(8);
+
+/// Lasers coming out elsewhere are labelled with the same letter on both ends, so a grid can
+/// need a letter for every second position on its border.
+pub const MAX_WIDTH_PLUS_HEIGHT: usize = observation::LETTER_COUNT;
+
+/// The dimensions of a (possibly rectangular) grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedSize"))]
+pub struct GridSize {
+    width: u8,
+    height: u8,
+}
+
+impl GridSize {
+    /// Panics if a side is empty or longer than 126 fields, or if both sides together are longer
+    /// than [MAX_WIDTH_PLUS_HEIGHT].
+    pub const fn new(width: u8, height: u8) -> Self {
+        assert!(width > 0 && width < i8::MAX as u8);
+        assert!(height > 0 && height < i8::MAX as u8);
+        assert!(width as usize + height as usize <= MAX_WIDTH_PLUS_HEIGHT);
+        Self { width, height }
+    }
+
+    pub const fn square(size: u8) -> Self {
+        Self::new(size, size)
+    }
 
-pub const GRID_SIZE: usize = 8;
+    /// Like [GridSize::new], but returns an error instead of panicking for unsupported sizes.
+    pub fn try_new(width: usize, height: usize) -> Result<Self, String> {
+        if !(1..i8::MAX as usize).contains(&width)
+            || !(1..i8::MAX as usize).contains(&height)
+            || width + height > MAX_WIDTH_PLUS_HEIGHT
+        {
+            return Err(format!("Unsupported grid size {}x{}", width, height));
+        }
+        Ok(Self::new(width as u8, height as u8))
+    }
+
+    /// The size of a grid given row by row. All rows must have the same length.
+    pub(crate) fn of_rows<T>(rows: &[Vec<T>]) -> Result<Self, String> {
+        let width = rows.first().map_or(0, Vec::len);
+        let size = Self::try_new(width, rows.len())?;
+        for (y, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                return Err(format!(
+                    "Row {} has {} fields instead of {}",
+                    y + 1,
+                    row.len(),
+                    width
+                ));
+            }
+        }
+        Ok(size)
+    }
+
+    pub const fn width(self) -> u8 {
+        self.width
+    }
+
+    pub const fn height(self) -> u8 {
+        self.height
+    }
+
+    /// Number of cells inside the grid.
+    pub fn cell_count(self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    pub fn contains(self, v: I8Vec2) -> bool {
+        v.x >= 0 && v.x < self.width as i8 && v.y >= 0 && v.y < self.height as i8
+    }
+
+    /// Number of lasers that can be shot into the grid moving in the given direction.
+    pub fn side_length(self, direction: Direction) -> u8 {
+        match direction {
+            Direction::Up | Direction::Down => self.width,
+            Direction::Left | Direction::Right => self.height,
+        }
+    }
+
+    /// Iterates over all cells, row by row.
+    pub fn cells(self) -> impl Iterator<Item = I8Vec2> {
+        (0..self.height as i8)
+            .flat_map(move |y| (0..self.width as i8).map(move |x| I8Vec2::new(x, y)))
+    }
+
+    /// Position of a cell inside row-major storage. The cell must be inside the grid.
+    pub(crate) fn index(self, v: I8Vec2) -> usize {
+        v.y as usize * self.width as usize + v.x as usize
+    }
+}
+
+impl Default for GridSize {
+    fn default() -> Self {
+        DEFAULT_SIZE
+    }
+}
+
+/// A deserialized size which still has to be checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedSize {
+    width: u8,
+    height: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedSize> for GridSize {
+    type Error = String;
+
+    fn try_from(size: UncheckedSize) -> Result<Self, String> {
+        Self::try_new(size.width as usize, size.height as usize)
+    }
+}
+
+/// What occupies a field of the grid. Classic puzzles only contain atoms, the other objects make
+/// variants of the game. Unlike atoms, objects only act on lasers moving into their own field.
+///
+/// The [solver](crate::solver) and the [exhaustive solver](crate::brute_force) only know atoms.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Cell {
+    #[default]
+    Empty,
+    /// Absorbs lasers running into it and deflects lasers passing its corners, see
+    /// [LaserTip::move_once](crate::laser::LaserTip::move_once).
+    Atom,
+    /// Absorbs lasers running into it, lasers passing by are not affected.
+    Absorber,
+    /// Sends lasers running into it back the way they came.
+    Reflector,
+    /// A diagonal mirror turning lasers by 90 degrees. Rising mirrors are drawn as `/`, falling
+    /// ones as `\`.
+    Mirror { rising: bool },
+    /// One end of a pair of portals with the same number, from 0 to 9. A laser moving into one end
+    /// continues from the other end in the same direction. Without its other end, a portal does
+    /// not affect lasers.
+    Portal(u8),
+}
+
+impl Cell {
+    /// The character used for the field in drawings of the grid. Portals are drawn as their
+    /// number, so only numbers up to 9 can be drawn.
+    pub fn symbol(self) -> char {
+        match self {
+            Cell::Empty => '.',
+            Cell::Atom => 'o',
+            Cell::Absorber => '*',
+            Cell::Reflector => '#',
+            Cell::Mirror { rising: true } => '/',
+            Cell::Mirror { rising: false } => '\\',
+            Cell::Portal(number) => {
+                char::from_digit(number as u32, 10).expect("Portal number too large to draw")
+            }
+        }
+    }
+
+    pub fn from_symbol(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(Cell::Empty),
+            'o' => Some(Cell::Atom),
+            '*' => Some(Cell::Absorber),
+            '#' => Some(Cell::Reflector),
+            '/' => Some(Cell::Mirror { rising: true }),
+            '\\' => Some(Cell::Mirror { rising: false }),
+            _ => c.to_digit(10).map(|number| Cell::Portal(number as u8)),
+        }
+    }
+}
+
+/// Cells are stored as their [symbol](Cell::symbol).
+#[cfg(feature = "serde")]
+impl serde::Serialize for Cell {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(self.symbol())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Cell {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let c = <char as serde::Deserialize>::deserialize(deserializer)?;
+        Cell::from_symbol(c)
+            .ok_or_else(|| serde::de::Error::custom(format!("Unknown field \"{}\"", c)))
+    }
+}
 
 /// The hidden inner secret of the game
-#[derive(Default, Debug, PartialEq, Eq, Clone)]
+///
+/// With the `serde` feature, grids are stored as a 2D array of [cell symbols](Cell::symbol), row
+/// by row. Use the `bitboard` module to store them as a bitboard instead.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "Vec<Vec<Cell>>", try_from = "Vec<Vec<Cell>>")
+)]
 pub struct AtomGrid {
-    atoms: [[bool; GRID_SIZE]; GRID_SIZE],
+    size: GridSize,
+    cells: Vec<Cell>,
+}
+
+impl Default for AtomGrid {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE)
+    }
 }
 
 impl AtomGrid {
+    /// Creates an empty grid of the given size.
+    pub fn new(size: GridSize) -> Self {
+        Self {
+            size,
+            cells: vec![Cell::Empty; size.cell_count()],
+        }
+    }
+
+    pub fn size(&self) -> GridSize {
+        self.size
+    }
+
+    /// Whether there is an atom at the given position. Other objects do not count.
     pub fn get(&self, v: I8Vec2) -> bool {
-        v.in_grid() && self.atoms[v.x as usize][v.y as usize]
+        self.cell(v) == Cell::Atom
     }
+
+    /// Places an atom at the given position or empties it.
     pub fn set(&mut self, v: I8Vec2, value: bool) {
-        if v.in_grid() {
-            self.atoms[v.x as usize][v.y as usize] = value;
+        self.write(v, if value { Cell::Atom } else { Cell::Empty });
+    }
+
+    /// What occupies the given position. Positions outside the grid are empty.
+    pub fn cell(&self, v: I8Vec2) -> Cell {
+        if v.in_grid(self.size) {
+            self.cells[self.size.index(v)]
         } else {
-            panic!("Out of bounds. Writing {} to {:?}", value, v);
+            Cell::Empty
         }
     }
 
-    pub fn random(atom_count: u8) -> Self {
-        let mut this = Self::default();
+    /// Places an object at the given position. Only portals numbered 0 to 9 can be placed, and
+    /// only two ends of each.
+    pub fn set_cell(&mut self, v: I8Vec2, cell: Cell) -> Result<(), String> {
+        if let Cell::Portal(number) = cell {
+            if number > 9 {
+                return Err(format!(
+                    "Portal {} can not be drawn, only 0 to 9 can",
+                    number
+                ));
+            }
+            let ends = self
+                .size
+                .cells()
+                .filter(|&w| w != v && self.cell(w) == cell)
+                .count();
+            if ends >= 2 {
+                return Err(format!("Portal {} has two ends already", number));
+            }
+        }
+        self.write(v, cell);
+        Ok(())
+    }
+
+    fn write(&mut self, v: I8Vec2, cell: Cell) {
+        if v.in_grid(self.size) {
+            let index = self.size.index(v);
+            self.cells[index] = cell;
+        } else {
+            panic!("Out of bounds. Writing {:?} to {:?}", cell, v);
+        }
+    }
+
+    /// The other end of the portal at the given position, or `None` if there is no portal or it
+    /// has no other end.
+    pub fn portal_exit(&self, v: I8Vec2) -> Option<I8Vec2> {
+        let portal @ Cell::Portal(_) = self.cell(v) else {
+            return None;
+        };
+        self.size
+            .cells()
+            .find(|&w| w != v && self.cell(w) == portal)
+    }
+
+    /// Places atoms using the given random number generator, so a seeded generator always
+    /// produces the same grid.
+    pub fn random(size: GridSize, atom_count: u8, rng: &mut impl Rng) -> Self {
+        assert!(
+            atom_count as usize <= size.cell_count(),
+            "Can not place {} atoms on {} cells",
+            atom_count,
+            size.cell_count()
+        );
+        let mut this = Self::new(size);
         let mut placed_down = 0;
         while placed_down < atom_count {
-            let v = I8Vec2::random();
-            if !this.get(v) {
+            let v = I8Vec2::random(size, rng);
+            if this.cell(v) == Cell::Empty {
                 this.set(v, true);
                 placed_down += 1;
             }
@@ -34,27 +314,39 @@ impl AtomGrid {
         this
     }
 
-    pub fn as_bitboard(&self) -> u64 {
+    /// The grid [AtomGrid::random] creates from a generator seeded with `seed`. Seeds stay valid as
+    /// long as the version of the `rand` crate does not change.
+    pub fn from_seed(size: GridSize, atom_count: u8, seed: u64) -> Self {
+        Self::random(size, atom_count, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Packs the grid into an integer, one bit per cell. The top left cell ends up in the most
+    /// significant used bit. Only grids with at most 128 cells can be packed, and only atoms are
+    /// kept, other objects are lost.
+    pub fn as_bitboard(&self) -> u128 {
+        assert!(
+            self.size.cell_count() <= 128,
+            "Grid too large for a bitboard"
+        );
         let mut result = 0;
-        for y in 0..8 {
-            for x in 0..8 {
-                result <<= 1;
-                if self.atoms[x][y] {
-                    result |= 1;
-                }
+        for v in self.size.cells() {
+            result <<= 1;
+            if self.get(v) {
+                result |= 1;
             }
         }
         result
     }
 
-    pub fn from_bitboard(bitboard: u64) -> Self {
-        let mut this = Self::default();
+    pub fn from_bitboard(size: GridSize, bitboard: u128) -> Self {
+        assert!(size.cell_count() <= 128, "Grid too large for a bitboard");
+        let mut this = Self::new(size);
         let mut bitboard = bitboard;
-        for y in 0..8 {
-            for x in 0..8 {
-                this.atoms[7 - x][7 - y] = bitboard & 1 == 1;
-                bitboard >>= 1;
+        for index in (0..size.cell_count()).rev() {
+            if bitboard & 1 == 1 {
+                this.cells[index] = Cell::Atom;
             }
+            bitboard >>= 1;
         }
         this
     }
@@ -62,13 +354,9 @@ impl AtomGrid {
 
 impl Display for AtomGrid {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
-        for y in 0..8 {
-            for x in 0..8 {
-                if self.atoms[x][y] {
-                    f.write_str(" o")?;
-                } else {
-                    f.write_str(" .")?;
-                }
+        for y in 0..self.size.height as i8 {
+            for x in 0..self.size.width as i8 {
+                write!(f, " {}", self.cell(I8Vec2::new(x, y)).symbol())?;
             }
             f.write_str("\n")?;
         }
@@ -76,18 +364,220 @@ impl Display for AtomGrid {
     }
 }
 
+/// The cells of the grid, row by row.
+impl From<AtomGrid> for Vec<Vec<Cell>> {
+    fn from(grid: AtomGrid) -> Self {
+        grid.cells
+            .chunks(grid.size.width as usize)
+            .map(<[Cell]>::to_vec)
+            .collect()
+    }
+}
+
+/// Builds a grid from its cells, row by row. Portals must come in pairs.
+impl TryFrom<Vec<Vec<Cell>>> for AtomGrid {
+    type Error = String;
+
+    fn try_from(rows: Vec<Vec<Cell>>) -> Result<Self, String> {
+        let size = GridSize::of_rows(&rows)?;
+        let this = AtomGrid {
+            size,
+            cells: rows.concat(),
+        };
+        for number in 0..10 {
+            let count = this
+                .cells
+                .iter()
+                .filter(|&&c| c == Cell::Portal(number))
+                .count();
+            if count != 0 && count != 2 {
+                return Err(format!("Portal {} has {} ends instead of 2", number, count));
+            }
+        }
+        Ok(this)
+    }
+}
+
+/// Reads a grid in the format it is displayed in, one row per line with a symbol for every field,
+/// see [Cell::symbol]. Portals must come in pairs.
+impl FromStr for AtomGrid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let rows = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(y, line)| {
+                line.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .map(|c| {
+                        Cell::from_symbol(c)
+                            .ok_or_else(|| format!("Unknown field \"{}\" in row {}", c, y + 1))
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<Cell>>, String>>()?;
+        AtomGrid::try_from(rows)
+    }
+}
+
+/// Stores an [AtomGrid] as its size and [bitboard](AtomGrid::as_bitboard) instead of a 2D array,
+/// e.g. with `#[serde(with = "laser_puzzle::atom_grid::bitboard")]`. Only grids with at most 128
+/// cells and no objects besides atoms can be stored.
+#[cfg(feature = "serde")]
+pub mod bitboard {
+    use super::{AtomGrid, Cell, GridSize};
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Bitboard {
+        size: GridSize,
+        atoms: u128,
+    }
+
+    pub fn serialize<S: Serializer>(grid: &AtomGrid, serializer: S) -> Result<S::Ok, S::Error> {
+        if grid.size.cell_count() > 128 {
+            return Err(ser::Error::custom("Grid too large for a bitboard"));
+        }
+        if grid
+            .cells
+            .iter()
+            .any(|&c| c != Cell::Empty && c != Cell::Atom)
+        {
+            return Err(ser::Error::custom("Only atoms can be stored in a bitboard"));
+        }
+        let bitboard = Bitboard {
+            size: grid.size,
+            atoms: grid.as_bitboard(),
+        };
+        bitboard.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AtomGrid, D::Error> {
+        let Bitboard { size, atoms } = Bitboard::deserialize(deserializer)?;
+        let cell_count = size.cell_count();
+        if cell_count > 128 || (cell_count < 128 && atoms >> cell_count != 0) {
+            return Err(de::Error::custom(format!(
+                "Bitboard {} does not fit a {}x{} grid",
+                atoms, size.width, size.height
+            )));
+        }
+        Ok(AtomGrid::from_bitboard(size, atoms))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::observation::Observations;
 
     /// Ensures, that the bitboard packing and unpacking works.
     #[test]
     fn test_bitboard() {
-        for _ in 0..100 {
-            let grid = AtomGrid::random(5);
-            let bitboard = grid.as_bitboard();
-            let grid2 = AtomGrid::from_bitboard(bitboard);
-            assert_eq!(grid, grid2);
+        let mut rng = StdRng::seed_from_u64(0);
+        for size in [DEFAULT_SIZE, GridSize::square(5), GridSize::new(10, 6)] {
+            for _ in 0..100 {
+                let grid = AtomGrid::random(size, 5, &mut rng);
+                let bitboard = grid.as_bitboard();
+                let grid2 = AtomGrid::from_bitboard(size, bitboard);
+                assert_eq!(grid, grid2);
+            }
+        }
+    }
+
+    /// The bitboard layout of the classic grid must not change, puzzles are shared as bitboards.
+    #[test]
+    fn test_bitboard_layout() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 1 << 63);
+        assert!(grid.get(I8Vec2::new(0, 0)));
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 1);
+        assert!(grid.get(I8Vec2::new(7, 7)));
+        let grid = AtomGrid::from_bitboard(GridSize::new(3, 2), 0b001_000);
+        assert!(grid.get(I8Vec2::new(2, 0)));
+    }
+
+    #[test]
+    fn test_parse_grid() {
+        let text = " o . * #\n / \\ 1 .\n . 1 . o\n";
+        let grid: AtomGrid = text.parse().unwrap();
+        assert_eq!(grid.size(), GridSize::new(4, 3));
+        assert!(grid.get(I8Vec2::new(0, 0)));
+        assert!(!grid.get(I8Vec2::new(2, 0)));
+        assert_eq!(grid.cell(I8Vec2::new(2, 0)), Cell::Absorber);
+        assert_eq!(grid.cell(I8Vec2::new(1, 1)), Cell::Mirror { rising: false });
+        assert_eq!(grid.portal_exit(I8Vec2::new(2, 1)), Some(I8Vec2::new(1, 2)));
+        assert_eq!(grid.portal_exit(I8Vec2::new(0, 0)), None);
+        assert_eq!(grid.to_string(), text);
+
+        assert!("o . 1\n. . .".parse::<AtomGrid>().is_err());
+        assert!("o . x".parse::<AtomGrid>().is_err());
+        assert!("o .\n. . .".parse::<AtomGrid>().is_err());
+        assert!("".parse::<AtomGrid>().is_err());
+    }
+
+    #[test]
+    fn test_set_cell() {
+        let mut grid = AtomGrid::new(GridSize::new(3, 1));
+        assert!(grid.set_cell(I8Vec2::new(0, 0), Cell::Portal(10)).is_err());
+        grid.set_cell(I8Vec2::new(0, 0), Cell::Portal(4)).unwrap();
+        grid.set_cell(I8Vec2::new(1, 0), Cell::Portal(4)).unwrap();
+        assert!(grid.set_cell(I8Vec2::new(2, 0), Cell::Portal(4)).is_err());
+        grid.set_cell(I8Vec2::new(1, 0), Cell::Portal(4)).unwrap();
+        assert_eq!(grid.to_string(), " 4 4 .\n");
+    }
+
+    #[test]
+    fn test_grid_size() {
+        assert_eq!(GridSize::try_new(10, 11), Ok(GridSize::new(10, 11)));
+        assert!(GridSize::try_new(0, 5).is_err());
+        assert!(GridSize::try_new(11, 11).is_err());
+
+        // Every laser passes an empty grid, so all letters are needed.
+        let observations = Observations::observe_all(&AtomGrid::new(GridSize::new(10, 11)));
+        assert!(observations.to_string().contains('Z'));
+    }
+
+    #[test]
+    fn test_seed() {
+        let grid = AtomGrid::from_seed(DEFAULT_SIZE, 5, 7);
+        assert_eq!(grid, AtomGrid::from_seed(DEFAULT_SIZE, 5, 7));
+        assert_ne!(grid, AtomGrid::from_seed(DEFAULT_SIZE, 5, 8));
+        assert_eq!(grid.as_bitboard().count_ones(), 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let grid: AtomGrid = "o . /\n1 # 1".parse().unwrap();
+        let json = serde_json::to_string(&grid).unwrap();
+        assert_eq!(json, r##"[["o",".","/"],["1","#","1"]]"##);
+        assert_eq!(serde_json::from_str::<AtomGrid>(&json).unwrap(), grid);
+
+        assert!(serde_json::from_str::<AtomGrid>(r#"[["o","."],["."]]"#).is_err());
+        assert!(serde_json::from_str::<AtomGrid>(r#"[["1","."]]"#).is_err());
+        assert!(serde_json::from_str::<AtomGrid>(r#"[["x"]]"#).is_err());
+        assert!(serde_json::from_str::<GridSize>(r#"{"width":0,"height":3}"#).is_err());
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Puzzle {
+            #[serde(with = "crate::atom_grid::bitboard")]
+            grid: AtomGrid,
         }
+        let puzzle = Puzzle {
+            grid: AtomGrid::from_bitboard(GridSize::new(3, 2), 0b100_001),
+        };
+        let json = serde_json::to_string(&puzzle).unwrap();
+        assert_eq!(
+            json,
+            r#"{"grid":{"size":{"width":3,"height":2},"atoms":33}}"#
+        );
+        assert_eq!(serde_json::from_str::<Puzzle>(&json).unwrap(), puzzle);
+
+        // Atoms outside the grid and other objects can not be stored.
+        let outside = r#"{"grid":{"size":{"width":3,"height":2},"atoms":64}}"#;
+        assert!(serde_json::from_str::<Puzzle>(outside).is_err());
+        let grid = "o #".parse().unwrap();
+        assert!(serde_json::to_string(&Puzzle { grid }).is_err());
     }
 }
@@ -0,0 +1,191 @@
+//! An exhaustive solver that enumerates every atom grid consistent with the observations.
+//!
+//! The local deduction rules of the [solver] are used first to rule out cells, so
+//! only the cells which are still unknown need to be enumerated.
+
+use crate::atom_grid::AtomGrid;
+use crate::observation::Observations;
+use crate::solver;
+use crate::solver::GridKnowledge::{Atom, Unknown};
+
+/// Finds all atom grids with exactly `atom_count` atoms that produce the given observations.
+/// Border positions that were not probed tell nothing, any result is fine there. The fewer lasers
+/// were fired, the more grids have to be checked.
+pub fn find_all_solutions(observations: &Observations, atom_count: u8) -> Vec<AtomGrid> {
+    find_solutions(observations, atom_count, usize::MAX)
+}
+
+/// Like [find_all_solutions], but stops after `limit` solutions have been found. Use a limit of
+/// two to check whether a puzzle is uniquely solvable.
+pub fn find_solutions(observations: &Observations, atom_count: u8, limit: usize) -> Vec<AtomGrid> {
+    let size = observations.size();
+    if limit == 0 {
+        return vec![];
+    }
+    assert!(
+        size.cell_count() <= 128,
+        "Brute force only works on grids which fit into a bitboard"
+    );
+    let Ok(knowledge) = solver::solve_as_much_as_you_can(observations) else {
+        // Contradicting observations can not be produced by any grid.
+        return vec![];
+    };
+
+    // Bit of each cell inside the bitboard, see AtomGrid::as_bitboard.
+    let bit = |index: usize| 1u128 << (size.cell_count() - 1 - index);
+
+    let mut fixed_atoms = 0u128;
+    let mut fixed_atom_count = 0;
+    let mut candidates = vec![];
+    for (index, v) in size.cells().enumerate() {
+        match knowledge.get(v) {
+            Atom => {
+                fixed_atoms |= bit(index);
+                fixed_atom_count += 1;
+            }
+            Unknown => candidates.push(bit(index)),
+            _ => {}
+        }
+    }
+    if fixed_atom_count > atom_count as usize {
+        return vec![];
+    }
+
+    let mut solutions = vec![];
+    for_each_combination(
+        candidates.len(),
+        atom_count as usize - fixed_atom_count,
+        |chosen| {
+            let bitboard = chosen
+                .iter()
+                .fold(fixed_atoms, |board, &i| board | candidates[i]);
+            let grid = AtomGrid::from_bitboard(size, bitboard);
+            if observations.is_consistent_with(&grid) {
+                solutions.push(grid);
+            }
+            solutions.len() < limit
+        },
+    );
+    solutions
+}
+
+/// Calls `visit` with every sorted selection of `k` indices out of `0..n` until it returns false.
+fn for_each_combination(n: usize, k: usize, mut visit: impl FnMut(&[usize]) -> bool) {
+    if k > n {
+        return;
+    }
+    let mut indices: Vec<usize> = (0..k).collect();
+    loop {
+        if !visit(&indices) {
+            return;
+        }
+        // Find the rightmost index that can still be moved to the right.
+        let Some(i) = (0..k).rev().find(|&i| indices[i] != i + n - k) else {
+            return;
+        };
+        indices[i] += 1;
+        for j in i + 1..k {
+            indices[j] = indices[j - 1] + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::{GridSize, DEFAULT_SIZE};
+    use crate::i8vec2::I8Vec2;
+    use crate::laser::Direction::*;
+    use crate::laser::LaserTip;
+    use crate::observation::NOT_PROBED;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_combinations() {
+        let mut seen = vec![];
+        for_each_combination(5, 3, |c| {
+            seen.push(c.to_vec());
+            true
+        });
+        assert_eq!(seen.len(), 10);
+        assert_eq!(seen[0], vec![0, 1, 2]);
+        assert_eq!(seen[9], vec![2, 3, 4]);
+
+        let mut count = 0;
+        for_each_combination(4, 0, |_| {
+            count += 1;
+            true
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_finds_hidden_grid() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for size in [DEFAULT_SIZE, GridSize::new(6, 4)] {
+            for _ in 0..10 {
+                let grid = AtomGrid::random(size, 4, &mut rng);
+                let observations = Observations::observe_all(&grid);
+                let solutions = find_all_solutions(&observations, 4);
+
+                assert!(solutions.contains(&grid), "Missing {}", grid);
+                for solution in solutions {
+                    let other = Observations::observe_all(&solution);
+                    assert_eq!(observations.sides, other.sides);
+                }
+            }
+        }
+    }
+
+    /// The four atoms around the center of the grid hide the center, only the atom count tells
+    /// the two grids apart.
+    #[test]
+    fn test_ambiguous_puzzle() {
+        let size = GridSize::square(5);
+        let mut grid = AtomGrid::new(size);
+        for v in [(2, 1), (1, 2), (3, 2), (2, 3)] {
+            grid.set(I8Vec2::new(v.0, v.1), true);
+        }
+        let observations = Observations::observe_all(&grid);
+        let mut with_center = grid.clone();
+        with_center.set(I8Vec2::new(2, 2), true);
+
+        assert_eq!(find_all_solutions(&observations, 4), vec![grid]);
+        assert_eq!(find_all_solutions(&observations, 5), vec![with_center]);
+        assert_eq!(find_solutions(&observations, 4, 0).len(), 0);
+    }
+
+    /// Without any lasers, every grid with the right number of atoms is a solution. Each laser
+    /// fired only rules out the grids where it would show something else.
+    #[test]
+    fn test_sparse_observations() {
+        let size = GridSize::square(5);
+        assert_eq!(
+            find_all_solutions(&Observations::new(size), 2).len(),
+            25 * 24 / 2
+        );
+
+        let lasers = [(0, Right), (2, Down), (4, Left), (1, Up)];
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..20 {
+            let grid = AtomGrid::random(size, 3, &mut rng);
+            let mut observations = Observations::new(size);
+            for (shift, direction) in lasers {
+                if observations.get(direction, shift) == NOT_PROBED {
+                    observations.probe(LaserTip::new(shift, direction, size), &grid);
+                }
+            }
+            let solutions = find_all_solutions(&observations, 3);
+            assert!(solutions.contains(&grid), "Missing {}", grid);
+            for solution in solutions {
+                // Firing the same lasers shows the same, no matter what the others would show.
+                let mut other = Observations::new(size);
+                for probe in observations.history() {
+                    other.probe(probe.laser, &solution);
+                }
+                assert_eq!(other, observations);
+            }
+        }
+    }
+}
@@ -0,0 +1,734 @@
+//! The command line interface of the binary.
+
+use laser_puzzle::atom_grid::{AtomGrid, GridSize, DEFAULT_SIZE};
+use laser_puzzle::brute_force;
+use laser_puzzle::difficulty;
+use laser_puzzle::difficulty::{Difficulty, Rating};
+use laser_puzzle::game;
+use laser_puzzle::game::Game;
+use laser_puzzle::generator;
+use laser_puzzle::laser::{Direction, LaserTip, Rules, TraversalResult};
+use laser_puzzle::observation;
+use laser_puzzle::observation::Observations;
+use laser_puzzle::session::GameSession;
+use laser_puzzle::solver;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const USAGE: &str = "Usage: laser-puzzle [command] [options]
+
+Commands:
+  generate   Create a uniquely solvable puzzle and print it together with its bitboard.
+  solve      Show what can be derived about a puzzle and list all its solutions.
+  play       Play a random puzzle in the terminal. This is the default.
+  verify     Check whether a grid is a solution of a puzzle.
+  trace      Follow lasers through a puzzle step by step.
+  replay     Show a saved game laser by laser.
+  help       Show this help.
+
+Options:
+  --atoms N          Number of atoms to hide (default 5).
+  --width W          Width of the grid (default 8).
+  --height H         Height of the grid (default 8). Width and height add up to 21 at most.
+  --seed S           Seed for the random puzzle, the same seed gives the same puzzle.
+  --daily            Use today's seed, so everyone gets the same puzzle today.
+  --difficulty D     Only generate easy, medium or hard puzzles.
+  --puzzle BITBOARD  The puzzle, given as bitboard of its hidden grid.
+  --observations F   The puzzle, given as observations in a text file, or - to read stdin.
+                     The grid size comes from the file, --atoms gives the number of atoms.
+  --guess BITBOARD   The grid to verify against the puzzle.
+  --grid F           The grid to trace, drawn in a text file, or - to read stdin. Besides
+                     atoms (o) it may contain absorbers (*), reflectors (#), mirrors (/ and \\)
+                     and pairs of portals (0-9).
+  --solution         Also print the hidden atoms when generating.
+  --minimal          Only show the lasers needed to solve the generated puzzle.
+  --laser SIDE,I     A laser to trace, e.g. left,3. May be repeated, default is all lasers.
+  --rules R          How lasers move: classic (default) or a comma separated list of
+                     no-edge-reflection and no-absorption.
+  --game F           Continue the game saved in a file, or start a new one there. The game
+                     is saved when you quit or guess. A saved game keeps its own size, atoms
+                     and rules, so leave those options out when continuing it.
+";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Subcommand {
+    Generate,
+    Solve,
+    Play,
+    Verify,
+    Trace,
+    Replay,
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub size: GridSize,
+    pub atoms: u8,
+    pub seed: Option<u64>,
+    pub difficulty: Option<Difficulty>,
+    pub puzzle: Option<u128>,
+    pub observations: Option<String>,
+    pub guess: Option<u128>,
+    pub grid: Option<String>,
+    pub show_solution: bool,
+    pub minimal: bool,
+    pub lasers: Vec<(u8, Direction)>,
+    pub rules: Rules,
+    pub game: Option<String>,
+    /// The options given which describe a new puzzle. A saved game brings its own puzzle.
+    pub puzzle_options: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            size: DEFAULT_SIZE,
+            atoms: 5,
+            seed: None,
+            difficulty: None,
+            puzzle: None,
+            observations: None,
+            guess: None,
+            grid: None,
+            show_solution: false,
+            minimal: false,
+            lasers: vec![],
+            rules: Rules::CLASSIC,
+            game: None,
+            puzzle_options: vec![],
+        }
+    }
+}
+
+/// Parses the arguments following the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<(Subcommand, Options), String> {
+    let mut args = args.into_iter().peekable();
+    let subcommand = match args.peek().map(String::as_str) {
+        Some("generate") => Subcommand::Generate,
+        Some("solve") => Subcommand::Solve,
+        Some("play") => Subcommand::Play,
+        Some("verify") => Subcommand::Verify,
+        Some("trace") => Subcommand::Trace,
+        Some("replay") => Subcommand::Replay,
+        Some("help" | "--help" | "-h") => Subcommand::Help,
+        Some(other) if !other.starts_with("--") => {
+            return Err(format!("Unknown command \"{}\"", other))
+        }
+        _ => Subcommand::Play,
+    };
+    if args.peek().is_some_and(|arg| !arg.starts_with("--")) {
+        args.next();
+    }
+
+    let mut options = Options::default();
+    let (mut width, mut height) = (DEFAULT_SIZE.width(), DEFAULT_SIZE.height());
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if matches!(
+            arg.as_str(),
+            "--atoms" | "--width" | "--height" | "--seed" | "--daily" | "--difficulty" | "--rules"
+        ) {
+            options.puzzle_options.push(arg.clone());
+        }
+        match arg.as_str() {
+            "--atoms" => options.atoms = parse_number(&value()?)?,
+            "--width" => width = parse_number(&value()?)?,
+            "--height" => height = parse_number(&value()?)?,
+            "--seed" => options.seed = Some(parse_number(&value()?)?),
+            "--daily" => options.seed = Some(daily_seed()),
+            "--difficulty" => options.difficulty = Some(value()?.parse()?),
+            "--puzzle" => options.puzzle = Some(parse_number(&value()?)?),
+            "--observations" => options.observations = Some(value()?),
+            "--guess" => options.guess = Some(parse_number(&value()?)?),
+            "--grid" => options.grid = Some(value()?),
+            "--solution" => options.show_solution = true,
+            "--minimal" => options.minimal = true,
+            "--laser" => options.lasers.push(parse_laser(&value()?)?),
+            "--rules" => options.rules = value()?.parse()?,
+            "--game" => options.game = Some(value()?),
+            _ => return Err(format!("Unknown option \"{}\"", arg)),
+        }
+    }
+
+    options.size = GridSize::try_new(width as usize, height as usize)?;
+    if options.atoms as usize > options.size.cell_count() {
+        return Err(format!(
+            "{} atoms do not fit into a {}x{} grid",
+            options.atoms, width, height
+        ));
+    }
+    let needs_bitboard = match subcommand {
+        Subcommand::Generate | Subcommand::Solve | Subcommand::Verify | Subcommand::Trace => true,
+        Subcommand::Play | Subcommand::Replay | Subcommand::Help => false,
+    };
+    if needs_bitboard
+        && options.observations.is_none()
+        && options.grid.is_none()
+        && options.size.cell_count() > 128
+    {
+        return Err("Bitboards only support grids with up to 128 fields".to_string());
+    }
+    if options.difficulty.is_some() && options.size.cell_count() > 128 {
+        return Err("Difficulties need a grid with up to 128 fields".to_string());
+    }
+    if options.difficulty.is_some() && options.rules != Rules::CLASSIC {
+        return Err("Difficulties are only rated for the classic rules".to_string());
+    }
+    if options.puzzle.is_some() && options.observations.is_some() {
+        return Err("Use either --puzzle or --observations".to_string());
+    }
+    // With --observations, the grid size is only known after reading the file.
+    if options.observations.is_none() {
+        for bitboard in [options.puzzle, options.guess].into_iter().flatten() {
+            if !fits(bitboard, options.size) {
+                return Err(format!("Bitboard {} is too large for the grid", bitboard));
+            }
+        }
+    }
+    if matches!(subcommand, Subcommand::Solve | Subcommand::Verify)
+        && options.puzzle.is_none()
+        && options.observations.is_none()
+    {
+        return Err("Missing --puzzle or --observations".to_string());
+    }
+    if subcommand == Subcommand::Verify && options.guess.is_none() {
+        return Err("Missing --guess".to_string());
+    }
+    if subcommand == Subcommand::Trace && options.puzzle.is_none() && options.grid.is_none() {
+        return Err("Missing --puzzle or --grid".to_string());
+    }
+    if subcommand == Subcommand::Replay && options.game.is_none() {
+        return Err("Missing --game".to_string());
+    }
+    if options.puzzle.is_some() && options.grid.is_some() {
+        return Err("Use either --puzzle or --grid".to_string());
+    }
+    // With --grid, the grid size is only known after reading the file.
+    if options.grid.is_none() {
+        check_lasers(&options.lasers, options.size)?;
+    }
+
+    Ok((subcommand, options))
+}
+
+fn check_lasers(lasers: &[(u8, Direction)], size: GridSize) -> Result<(), String> {
+    for &(shift, direction) in lasers {
+        if shift >= size.side_length(direction) {
+            return Err(format!(
+                "There is no laser {} on the {} side",
+                shift,
+                game::side_name(direction)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn fits(bitboard: u128, size: GridSize) -> bool {
+    size.cell_count() >= 128 || bitboard >> size.cell_count() == 0
+}
+
+/// Parses a laser given as side and index, e.g. "left,3".
+fn parse_laser(value: &str) -> Result<(u8, Direction), String> {
+    let (side, index) = value
+        .split_once(',')
+        .ok_or_else(|| format!("\"{}\" is not a laser like left,3", value))?;
+    Ok((parse_number(index)?, game::parse_side(side)?))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a valid number", value))
+}
+
+/// The seed of today's puzzle, the number of days since 1970-01-01 (UTC). Everyone playing the
+/// daily puzzle with the same options gets the same grid.
+pub fn daily_seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before 1970");
+    now.as_secs() / (24 * 60 * 60)
+}
+
+/// The seed given in the options. Without one, a random seed is picked. The seed is printed so the
+/// puzzle can be reproduced later.
+fn print_seed(options: &Options, output: &mut impl Write) -> io::Result<u64> {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    writeln!(output, "Seed: {}", seed)?;
+    Ok(seed)
+}
+
+/// Creates the grid for the given options. Grids that fit into a bitboard are always uniquely
+/// solvable.
+fn random_grid(options: &Options, seed: u64) -> io::Result<AtomGrid> {
+    if options.size.cell_count() > 128 {
+        // Too large for the exhaustive solver, so the puzzle may have several solutions.
+        return Ok(AtomGrid::from_seed(options.size, options.atoms, seed));
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let grid = match options.difficulty {
+        Some(difficulty) => {
+            difficulty::puzzle_with_difficulty(options.size, options.atoms, difficulty, &mut rng)
+        }
+        None => generator::unique_puzzle_with_rules(
+            options.size,
+            options.atoms,
+            options.rules,
+            &mut rng,
+        ),
+    };
+    grid.ok_or_else(|| {
+        io::Error::other(format!(
+            "Found no uniquely solvable {}puzzle with {} atoms",
+            options
+                .difficulty
+                .map_or(String::new(), |d| format!("{} ", d)),
+            options.atoms
+        ))
+    })
+}
+
+/// Minutes and seconds.
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Reads the file at the given path, or the input if the path is "-".
+fn read_text(path: &str, input: &mut impl BufRead) -> io::Result<String> {
+    if path == "-" {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+/// Reads the puzzle given by --puzzle or --observations. Returns the observations and the number
+/// of hidden atoms.
+fn read_puzzle(options: &Options, input: &mut impl BufRead) -> io::Result<(Observations, u8)> {
+    if let Some(puzzle) = options.puzzle {
+        let grid = AtomGrid::from_bitboard(options.size, puzzle);
+        return Ok((
+            Observations::observe_all_with_rules(&grid, options.rules),
+            puzzle.count_ones() as u8,
+        ));
+    }
+
+    let path = options.observations.as_ref().expect("No puzzle given");
+    let mut observations: Observations = read_text(path, input)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if observations.size().cell_count() > 128 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Bitboards only support grids with up to 128 fields",
+        ));
+    }
+    observations.set_rules(options.rules);
+    Ok((observations, options.atoms))
+}
+
+/// Runs a subcommand. Returns false if verification failed.
+pub fn run(
+    subcommand: Subcommand,
+    options: &Options,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<bool> {
+    match subcommand {
+        Subcommand::Generate => {
+            let seed = print_seed(options, output)?;
+            let grid = random_grid(options, seed)?;
+            let observations = if options.minimal {
+                generator::minimal_observations(
+                    &grid,
+                    options.rules,
+                    &mut StdRng::seed_from_u64(seed),
+                )
+            } else {
+                Observations::observe_all_with_rules(&grid, options.rules)
+            };
+            writeln!(output, "Bitboard: {}", grid.as_bitboard())?;
+            let rating = Rating::new(&observations, options.atoms)
+                .expect("Observations of a real grid do not contradict each other");
+            writeln!(output, "Difficulty: {}", rating.difficulty())?;
+            let shown = if options.show_solution {
+                grid
+            } else {
+                AtomGrid::new(options.size)
+            };
+            write!(
+                output,
+                "{}",
+                observation::draw(&shown, &observations).expect("Failed to draw puzzle")
+            )?;
+        }
+        Subcommand::Solve => {
+            let (observations, atom_count) = read_puzzle(options, input)?;
+            match solver::solve_as_much_as_you_can(&observations) {
+                Ok(knowledge) => {
+                    writeln!(
+                        output,
+                        "{}",
+                        solver::draw(&knowledge, &observations)
+                            .expect("Failed to draw solver state")
+                    )?;
+                    let rating = Rating::new(&observations, atom_count)
+                        .expect("The solver found no contradiction");
+                    writeln!(
+                        output,
+                        "Difficulty: {} (score {}, {} rounds, at most {} grids to try)",
+                        rating.difficulty(),
+                        rating.score(),
+                        rating.rounds,
+                        rating.search_space_bound
+                    )?;
+                }
+                Err(contradiction) => writeln!(output, "Contradiction: {}", contradiction)?,
+            }
+
+            let solutions = brute_force::find_all_solutions(&observations, atom_count);
+            writeln!(output, "Number of solutions: {}", solutions.len())?;
+            for solution in &solutions {
+                writeln!(
+                    output,
+                    "\nBitboard: {}\n{}",
+                    solution.as_bitboard(),
+                    solution
+                )?;
+            }
+        }
+        Subcommand::Play => {
+            let mut session = match &options.game {
+                Some(path) if Path::new(path).exists() => {
+                    if let Some(option) = options.puzzle_options.first() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "{} can not be used to continue the game saved in {}",
+                                option, path
+                            ),
+                        ));
+                    }
+                    writeln!(output, "Continuing the game saved in {}.", path)?;
+                    GameSession::load(path)?
+                }
+                _ => {
+                    let seed = print_seed(options, output)?;
+                    GameSession::new(Game::new(random_grid(options, seed)?, options.rules))
+                }
+            };
+            if session.game().is_finished() {
+                writeln!(output, "This game is over, use \"replay\" to look at it.")?;
+                return Ok(true);
+            }
+            session.run(input, output)?;
+            if let Some(path) = &options.game {
+                session.save(path)?;
+                writeln!(
+                    output,
+                    "Played for {}, the game is saved in {}.",
+                    format_time(session.elapsed()),
+                    path
+                )?;
+            }
+        }
+        Subcommand::Replay => {
+            let session = GameSession::load(options.game.as_ref().unwrap())?;
+            let game = session.game();
+            let empty = AtomGrid::new(game.hidden().size());
+            for (i, step) in session.replay().iter().enumerate() {
+                writeln!(
+                    output,
+                    "Laser {}: fire {} {} shows {}.\n{}",
+                    i + 1,
+                    game::side_name(step.direction),
+                    step.shift,
+                    step.observation,
+                    observation::draw(&empty, &step.observations).expect("Failed to draw puzzle")
+                )?;
+            }
+            if game.is_finished() {
+                let result = game.guess();
+                let score = game.score();
+                writeln!(
+                    output,
+                    "{}\n{} correct, {} wrong and {} missed atoms. Score: {}.",
+                    observation::draw(game.hidden(), game.observations())
+                        .expect("Failed to draw solution"),
+                    result.correct,
+                    result.wrong,
+                    result.missed,
+                    score.total()
+                )?;
+            } else {
+                writeln!(output, "The game is not over yet.")?;
+            }
+            writeln!(output, "Played for {}.", format_time(session.elapsed()))?;
+        }
+        Subcommand::Verify => {
+            let (observations, atom_count) = read_puzzle(options, input)?;
+            let guess = options.guess.unwrap();
+            if !fits(guess, observations.size()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Bitboard {} is too large for the grid", guess),
+                ));
+            }
+            let valid = guess.count_ones() == atom_count as u32
+                && observations
+                    .is_consistent_with(&AtomGrid::from_bitboard(observations.size(), guess));
+            if valid {
+                writeln!(output, "The grid is a solution of the puzzle.")?;
+            } else {
+                writeln!(output, "The grid is not a solution of the puzzle.")?;
+            }
+            return Ok(valid);
+        }
+        Subcommand::Trace => {
+            let grid = match &options.grid {
+                Some(path) => read_text(path, input)?
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                None => AtomGrid::from_bitboard(options.size, options.puzzle.unwrap()),
+            };
+            let size = grid.size();
+            check_lasers(&options.lasers, size)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let lasers = if options.lasers.is_empty() {
+                let observations = Observations::new(size);
+                observations
+                    .iter()
+                    .into_iter()
+                    .map(|(direction, shift, _)| (shift, direction))
+                    .collect()
+            } else {
+                options.lasers.clone()
+            };
+            for &(shift, direction) in &lasers {
+                writeln!(
+                    output,
+                    "Laser {} from the {} side:",
+                    shift,
+                    game::side_name(direction)
+                )?;
+                let laser = LaserTip::new(shift, direction, size);
+                for step in laser.trace(&grid, &options.rules) {
+                    let from = step.from.position();
+                    write!(output, "  ({}, {}) {}", from.x, from.y, step.rule)?;
+                    match step.to {
+                        Some(to) => {
+                            writeln!(output, " to ({}, {})", to.position().x, to.position().y)?
+                        }
+                        None => writeln!(output)?,
+                    }
+                }
+                match laser.traverse_grid(&grid, &options.rules) {
+                    TraversalResult::Absorbed { at } => {
+                        writeln!(output, "  Absorbed at ({}, {}).", at.x, at.y)?
+                    }
+                    TraversalResult::Reflected => writeln!(output, "  Reflected.")?,
+                    TraversalResult::Exited { side, shift } => writeln!(
+                        output,
+                        "  Leaves on the {} side at {}.",
+                        game::side_name(side),
+                        shift
+                    )?,
+                    TraversalResult::Looped => writeln!(output, "  Trapped in a loop.")?,
+                }
+            }
+            let tips: Vec<LaserTip> = lasers
+                .iter()
+                .map(|&(shift, direction)| LaserTip::new(shift, direction, size))
+                .collect();
+            write!(
+                output,
+                "\n{}",
+                observation::draw_paths(
+                    &grid,
+                    &Observations::observe_all_with_rules(&grid, options.rules),
+                    &tips
+                )
+                .expect("Failed to draw laser paths")
+            )?;
+        }
+        Subcommand::Help => output.write_all(USAGE.as_bytes())?,
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<(Subcommand, Options), String> {
+        parse_args(line.split_whitespace().map(String::from))
+    }
+
+    fn run_to_string(line: &str) -> (bool, String) {
+        run_with_input(line, "").unwrap()
+    }
+
+    fn run_with_input(line: &str, input: &str) -> io::Result<(bool, String)> {
+        let (subcommand, options) = args(line).unwrap();
+        let mut output = vec![];
+        let success = run(subcommand, &options, &mut input.as_bytes(), &mut output)?;
+        Ok((success, String::from_utf8(output).unwrap()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(""), Ok((Subcommand::Play, Options::default())));
+        let (subcommand, options) = args("generate --atoms 4 --seed 12 --width 10").unwrap();
+        assert_eq!(subcommand, Subcommand::Generate);
+        assert_eq!(options.atoms, 4);
+        assert_eq!(options.seed, Some(12));
+        assert_eq!(options.size, GridSize::new(10, 8));
+
+        assert!(args("solve").is_err());
+        assert!(args("verify --puzzle 12").is_err());
+        assert!(args("generate --atoms").is_err());
+        assert!(args("generate --atoms many").is_err());
+        assert!(args("generate --width 0").is_err());
+        assert!(args("generate --width 12 --height 12").is_err());
+        assert!(args("play --width 10 --height 11").is_ok());
+        assert!(args("play --width 12 --height 10").is_err());
+        assert!(args("solve --puzzle 16 --width 2 --height 2").is_err());
+        assert!(args("shuffle").is_err());
+        assert!(args("trace --laser left,3").is_err());
+        assert!(args("trace --puzzle 1 --laser left,8").is_err());
+        assert!(args("trace --puzzle 1 --laser middle,1").is_err());
+        let (_, options) = args("trace --puzzle 1 --laser top,2 --laser r,0").unwrap();
+        assert_eq!(options.lasers, [(2, Direction::Down), (0, Direction::Left)]);
+        assert!(args("generate --difficulty impossible").is_err());
+        assert!(args("play --difficulty hard --width 12 --height 12").is_err());
+        assert!(args("solve --puzzle 12 --observations puzzle.txt").is_err());
+        let (_, options) = args("solve --observations - --width 10 --height 11").unwrap();
+        assert_eq!(options.observations, Some("-".to_string()));
+        let (_, options) = args("play --rules no-absorption").unwrap();
+        assert!(!options.rules.absorption);
+        assert!(args("play --rules quantum").is_err());
+        assert!(args("play --rules no-absorption --difficulty easy").is_err());
+    }
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let (_, first) = run_to_string("generate --seed 42 --solution");
+        let (_, second) = run_to_string("generate --seed 42 --solution");
+        assert_eq!(first, second);
+        assert!(first.starts_with("Seed: 42\n"));
+        assert!(first.contains("Difficulty: "));
+        assert_eq!(first.matches(" o").count(), 5);
+
+        let (_, easy) = run_to_string("generate --seed 42 --difficulty easy");
+        assert!(easy.contains("Difficulty: easy"));
+
+        let (_, minimal) = run_to_string("generate --seed 42 --atoms 3 --width 6 --minimal");
+        let (_, full) = run_to_string("generate --seed 42 --atoms 3 --width 6");
+        assert_eq!(minimal.lines().nth(1), full.lines().nth(1));
+        assert!(minimal.matches('?').count() > full.matches('?').count());
+
+        let (_, daily) = run_to_string("generate --daily");
+        assert!(daily.starts_with(&format!("Seed: {}\n", daily_seed())));
+    }
+
+    #[test]
+    fn test_solve_and_verify() {
+        let (_, output) = run_to_string("solve --puzzle 35184640598018");
+        assert!(output.contains("Number of solutions: 1"));
+
+        assert!(run_to_string("verify --puzzle 35184640598018 --guess 35184640598018").0);
+        assert!(!run_to_string("verify --puzzle 35184640598018 --guess 35184640598019").0);
+    }
+
+    #[test]
+    fn test_solve_and_verify_observations() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let text = Observations::observe_all(&grid).to_string();
+
+        let (_, output) = run_with_input("solve --observations -", &text).unwrap();
+        assert!(output.contains("Number of solutions: 1"));
+        assert!(output.contains("Bitboard: 35184640598018"));
+
+        let verify = |guess: &str| {
+            let line = format!("verify --observations - --guess {}", guess);
+            run_with_input(&line, &text).unwrap().0
+        };
+        assert!(verify("35184640598018"));
+        assert!(!verify("35184640598019"));
+
+        let error = run_with_input("solve --observations -", "  ? ?\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_trace() {
+        let (_, output) = run_to_string("trace --puzzle 35184640598018 --laser left,2");
+        assert!(output.starts_with(
+            "Laser 2 from the left side:\n  (-1, 2) straight to (0, 2)\n  (0, 2) straight to (1, 2)\n  (1, 2) absorbed\n  Absorbed at (2, 2).\n"
+        ));
+        assert!(output.contains(" × → → o . . . . . ×\n"));
+        let (_, output) = run_to_string("trace --puzzle 35184640598018");
+        assert_eq!(output.matches("Laser").count(), 32);
+        let (_, output) =
+            run_to_string("trace --puzzle 35184640598018 --laser left,2 --rules no-absorption");
+        assert!(output.contains("  (1, 2) reflected to (0, 2)\n"));
+
+        let grid = " . . . .\n \\ . 1 .\n # . . *\n 1 / . .\n";
+        let (_, output) = run_with_input("trace --grid - --laser top,0", grid).unwrap();
+        assert!(output.contains("  (0, 0) deflected left to (0, 1)\n"));
+        assert!(output.contains("  (1, 1) teleported to (0, 3)\n"));
+        assert!(output.contains("  (0, 3) deflected left to (1, 3)\n"));
+        assert!(output.contains("  Leaves on the top side at 1.\n"));
+        let (_, output) = run_with_input("trace --grid - --laser left,2", grid).unwrap();
+        assert!(output.contains("  (-1, 2) reflected to (-2, 2)\n"));
+        assert!(args("trace --grid grid.txt --puzzle 1").is_err());
+        assert!(run_with_input("trace --grid - --laser left,4", grid).is_err());
+    }
+
+    #[test]
+    fn test_saved_game() {
+        let path =
+            std::env::temp_dir().join(format!("laser-puzzle-cli-{}.txt", std::process::id()));
+        let play = format!(
+            "play --seed 1 --width 5 --height 5 --atoms 2 --game {}",
+            path.display()
+        );
+        let (_, output) = run_with_input(&play, "fire left 2\nmark 1 1\nquit\n").unwrap();
+        assert!(output.contains("Seed: 1"));
+        assert!(output.contains(", the game is saved in "));
+
+        // The saved game brings its own puzzle.
+        let error = run_with_input(&play, "").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let play = format!("play --game {}", path.display());
+        let (_, output) = run_with_input(&play, "fire top 3\nguess\n").unwrap();
+        assert!(output.contains("Continuing the game saved in"));
+        assert!(!output.contains("Seed: 1"));
+        assert!(output.contains("1 of 2 atoms marked"));
+        let (_, output) = run_with_input(&play, "").unwrap();
+        assert!(output.contains("This game is over"));
+
+        let replay = format!("replay --game {}", path.display());
+        let (_, output) = run_to_string(&replay);
+        fs::remove_file(&path).unwrap();
+        assert!(output.contains("Laser 1: fire left 2 shows "));
+        assert!(output.contains("Laser 2: fire top 3 shows "));
+        assert!(!output.contains("Laser 3"));
+        assert!(output.contains("Score: "));
+        assert!(args("replay").is_err());
+        assert!(run_with_input(&replay, "").is_err());
+    }
+}
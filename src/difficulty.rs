@@ -0,0 +1,199 @@
+//! Estimates how hard a puzzle is for a human.
+//!
+//! The estimate follows the way the [solver](crate::solver) cracks the puzzle: puzzles that fall
+//! to a few rounds of local deduction are easy, puzzles where the rules leave several candidate
+//! grids that have to be tried one by one are hard.
+
+use crate::atom_grid::{AtomGrid, GridSize};
+use crate::generator;
+use crate::observation::Observations;
+use crate::solver::GridKnowledge::{Atom, Unknown};
+use crate::solver::{Contradiction, Solver};
+use rand::Rng;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Puzzles with a [Rating::score] up to this value are easy.
+pub const MAX_EASY_SCORE: u32 = 24;
+/// Puzzles with a [Rating::score] up to this value are medium, all others are hard.
+pub const MAX_MEDIUM_SCORE: u32 = 32;
+/// How many puzzles the generator creates while looking for the requested difficulty.
+pub const MAX_ATTEMPTS: usize = 200;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        })
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("Unknown difficulty \"{}\"", s)),
+        }
+    }
+}
+
+/// What it takes to solve a puzzle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rating {
+    /// Names of the solver rules which derived anything, in the order they first did so. Not all
+    /// of them may be needed, other rules could have found the same.
+    pub rules_used: Vec<&'static str>,
+    /// Rounds of local deduction until nothing new was found.
+    pub rounds: usize,
+    /// Fields that are still unknown after local deduction.
+    pub unknown_fields: usize,
+    /// Ways to place the remaining atoms on the unknown fields, no matter whether they fit the
+    /// observations. This bounds how many grids have to be tried one by one after local deduction.
+    /// A single one means local deduction solved the puzzle.
+    pub search_space_bound: u64,
+}
+
+impl Rating {
+    /// Rates the puzzle given by the observations with `atom_count` hidden atoms.
+    pub fn new(observations: &Observations, atom_count: u8) -> Result<Self, Contradiction> {
+        let (knowledge, statistics) = Solver::default().solve_with_statistics(observations)?;
+        let size = observations.size();
+        let known_atoms = size.cells().filter(|&v| knowledge.get(v) == Atom).count();
+        let unknown_fields = size
+            .cells()
+            .filter(|&v| knowledge.get(v) == Unknown)
+            .count();
+        Ok(Rating {
+            rules_used: statistics.rules_used,
+            rounds: statistics.rounds,
+            unknown_fields,
+            search_space_bound: binomial(
+                unknown_fields,
+                (atom_count as usize).saturating_sub(known_atoms),
+            ),
+        })
+    }
+
+    /// Whether the solver has to try grids after local deduction.
+    pub fn needs_search(&self) -> bool {
+        self.search_space_bound > 1
+    }
+
+    /// Combines everything into a single number, higher is harder. Each rule and each round of
+    /// deduction adds a point. If grids have to be tried, every doubling of the search space adds
+    /// two points.
+    pub fn score(&self) -> u32 {
+        let search_bits = if self.needs_search() {
+            u64::BITS - (self.search_space_bound - 1).leading_zeros()
+        } else {
+            0
+        };
+        (self.rules_used.len() + self.rounds) as u32 + 2 * search_bits
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        match self.score() {
+            score if score <= MAX_EASY_SCORE => Difficulty::Easy,
+            score if score <= MAX_MEDIUM_SCORE => Difficulty::Medium,
+            _ => Difficulty::Hard,
+        }
+    }
+}
+
+/// Rates the puzzle of the given grid with all lasers fired.
+pub fn rate(grid: &AtomGrid) -> Rating {
+    let atom_count = grid.size().cells().filter(|&v| grid.get(v)).count() as u8;
+    Rating::new(&Observations::observe_all(grid), atom_count)
+        .expect("Observations of a real grid do not contradict each other")
+}
+
+/// Creates a uniquely solvable puzzle of the given difficulty, see
+/// [generator::unique_puzzle]. Returns `None` if none was found after [MAX_ATTEMPTS] puzzles.
+pub fn puzzle_with_difficulty(
+    size: GridSize,
+    atom_count: u8,
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+) -> Option<AtomGrid> {
+    (0..MAX_ATTEMPTS)
+        .filter_map(|_| generator::unique_puzzle(size, atom_count, rng))
+        .find(|grid| rate(grid).difficulty() == difficulty)
+}
+
+/// Number of ways to choose `k` out of `n`, saturating at `u64::MAX`.
+fn binomial(n: usize, k: usize) -> u64 {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    let mut result: u128 = 1;
+    for i in 0..k {
+        // Stays an integer because it is the binomial coefficient of (n - k + i + 1, i + 1).
+        result = result * (n - k + i + 1) as u128 / (i + 1) as u128;
+        if result > u64::MAX as u128 {
+            return u64::MAX;
+        }
+    }
+    result as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::DEFAULT_SIZE;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_binomial() {
+        assert_eq!(binomial(5, 2), 10);
+        assert_eq!(binomial(5, 0), 1);
+        assert_eq!(binomial(2, 5), 0);
+        assert_eq!(binomial(64, 5), 7624512);
+        assert_eq!(binomial(128, 64), u64::MAX);
+    }
+
+    #[test]
+    fn test_rating() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let rating = rate(&grid);
+        assert_eq!(rating.rules_used.len(), 5);
+        assert_eq!(rating.rounds, 3);
+        assert_eq!(rating.unknown_fields, 20);
+        assert_eq!(rating.search_space_bound, binomial(20, 5));
+        assert!(rating.needs_search());
+        // 15504 grids need 14 bits.
+        assert_eq!(rating.score(), 5 + 3 + 2 * 14);
+        assert_eq!(rating.difficulty(), Difficulty::Hard);
+
+        // Without atoms, every laser passes straight through and frees all fields.
+        let rating = rate(&AtomGrid::new(DEFAULT_SIZE));
+        assert!(!rating.needs_search());
+        assert_eq!(rating.unknown_fields, 0);
+        assert_eq!(rating.difficulty(), Difficulty::Easy);
+    }
+
+    #[test]
+    fn test_puzzle_with_difficulty() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let grid = puzzle_with_difficulty(DEFAULT_SIZE, 5, difficulty, &mut rng).unwrap();
+            assert_eq!(rate(&grid).difficulty(), difficulty);
+            assert_eq!(difficulty.to_string().parse(), Ok(difficulty));
+        }
+        assert!("impossible".parse::<Difficulty>().is_err());
+    }
+}
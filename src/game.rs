@@ -0,0 +1,586 @@
+//! The interactive puzzle: the player fires lasers into a hidden atom grid, marks where they
+//! suspect atoms and finally submits the marks as their guess.
+
+use crate::advisor;
+use crate::advisor::{Advice, Strategy};
+use crate::atom_grid::AtomGrid;
+use crate::brute_force;
+use crate::i8vec2::I8Vec2;
+use crate::laser::Direction::{Down, Left, Right, Up};
+use crate::laser::{Direction, LaserTip, Rules, TraversalResult};
+use crate::observation;
+use crate::observation::{Observation, Observations, Probe, NOT_PROBED};
+use crate::score;
+use crate::score::Score;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+/// Hints are only given once at most this many grids are consistent with the observations, so
+/// they stay fast early in the game when almost every grid is possible.
+pub const MAX_HINT_CANDIDATES: usize = 1000;
+
+/// A running game. The atom grid stays hidden until the player submits a guess.
+pub struct Game {
+    hidden: AtomGrid,
+    atom_count: usize,
+    observations: Observations,
+    /// Lasers that were taken back, in the order they were taken back. They are still charged.
+    undone: Vec<Probe>,
+    marks: AtomGrid,
+    finished: bool,
+}
+
+/// Comparison of the player's marks with the hidden grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GuessResult {
+    /// Marks on an atom.
+    pub correct: usize,
+    /// Marks on an empty field.
+    pub wrong: usize,
+    /// Atoms without a mark.
+    pub missed: usize,
+}
+
+impl GuessResult {
+    pub fn is_solved(&self) -> bool {
+        self.wrong == 0 && self.missed == 0
+    }
+}
+
+impl Game {
+    /// Starts a game where the lasers follow the given rules.
+    pub fn new(hidden: AtomGrid, rules: Rules) -> Self {
+        let size = hidden.size();
+        Game {
+            atom_count: size.cells().filter(|&v| hidden.get(v)).count(),
+            observations: Observations::with_rules(size, rules),
+            undone: vec![],
+            marks: AtomGrid::new(size),
+            hidden,
+            finished: false,
+        }
+    }
+
+    pub fn atom_count(&self) -> usize {
+        self.atom_count
+    }
+
+    pub fn observations(&self) -> &Observations {
+        &self.observations
+    }
+
+    /// The grid the player has to find. Looking at it spoils the game.
+    pub fn hidden(&self) -> &AtomGrid {
+        &self.hidden
+    }
+
+    /// The fields the player marked as atoms.
+    pub fn marks(&self) -> &AtomGrid {
+        &self.marks
+    }
+
+    /// The lasers fired so far as shift and direction, in the order they were fired.
+    pub fn probes(&self) -> Vec<(u8, Direction)> {
+        let size = self.hidden.size();
+        self.observations
+            .history()
+            .iter()
+            .map(|probe| probe.laser.deconstruct(size).unwrap())
+            .collect()
+    }
+
+    /// The lasers taken back with [Game::undo] as shift and direction, in the order they were
+    /// taken back.
+    pub fn undone(&self) -> Vec<(u8, Direction)> {
+        let size = self.hidden.size();
+        self.undone
+            .iter()
+            .map(|probe| probe.laser.deconstruct(size).unwrap())
+            .collect()
+    }
+
+    /// Whether the player submitted their guess, see [Game::submit].
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Fires a laser entering the grid moving in the given direction. Returns `None` if the
+    /// result at this position is already known, firing again would not tell anything new.
+    pub fn fire(&mut self, shift: u8, direction: Direction) -> Option<Observation> {
+        let size = self.hidden.size();
+        assert!(shift < size.side_length(direction), "No such laser");
+        self.observations
+            .probe(LaserTip::new(shift, direction, size), &self.hidden)
+    }
+
+    /// Takes back the last laser, so its result is not shown any more. The player has seen the
+    /// result, so its points are still charged. Returns the laser as shift and direction, or
+    /// `None` if no laser was fired or the game is over.
+    pub fn undo(&mut self) -> Option<(u8, Direction)> {
+        if self.finished {
+            return None;
+        }
+        let probe = self.observations.undo()?;
+        self.undone.push(probe);
+        Some(probe.laser.deconstruct(self.hidden.size()).unwrap())
+    }
+
+    /// Points for all lasers fired so far, including the ones taken back.
+    pub fn probe_points(&self) -> u32 {
+        let undone: u32 = self
+            .undone
+            .iter()
+            .map(|probe| score::probe_cost(probe.observation))
+            .sum();
+        score::probe_points(&self.observations) + undone
+    }
+
+    /// Marks a field as suspected atom or removes the mark again.
+    pub fn toggle_mark(&mut self, v: I8Vec2) {
+        let marked = self.marks.get(v);
+        self.marks.set(v, !marked);
+    }
+
+    pub fn mark_count(&self) -> usize {
+        let size = self.marks.size();
+        size.cells().filter(|&v| self.marks.get(v)).count()
+    }
+
+    /// Compares the marks to the hidden grid.
+    pub fn guess(&self) -> GuessResult {
+        let mut result = GuessResult {
+            correct: 0,
+            wrong: 0,
+            missed: 0,
+        };
+        for v in self.hidden.size().cells() {
+            match (self.marks.get(v), self.hidden.get(v)) {
+                (true, true) => result.correct += 1,
+                (true, false) => result.wrong += 1,
+                (false, true) => result.missed += 1,
+                (false, false) => {}
+            }
+        }
+        result
+    }
+
+    /// Ends the game with the current marks as the player's guess.
+    pub fn submit(&mut self) -> GuessResult {
+        self.finished = true;
+        self.guess()
+    }
+
+    /// Suggests the laser that tells the most about the hidden grid. Also returns the number of
+    /// grids still possible. Returns `None` if the grid is too large to enumerate candidates, more
+    /// than [MAX_HINT_CANDIDATES] grids are possible or every laser was fired already.
+    pub fn hint(&self, strategy: Strategy) -> Option<(Advice, usize)> {
+        if self.hidden.size().cell_count() > 128 {
+            return None;
+        }
+        let candidates = brute_force::find_solutions(
+            &self.observations,
+            self.atom_count as u8,
+            MAX_HINT_CANDIDATES + 1,
+        );
+        if candidates.len() > MAX_HINT_CANDIDATES {
+            // Only some of the grids could be rated, which would favour some lasers.
+            return None;
+        }
+        let advice = advisor::best_probe(&self.observations, &candidates, strategy)?;
+        Some((advice, candidates.len()))
+    }
+
+    /// The lasers fired so far which would have shown something else if the atoms were where the
+    /// player marked them.
+    pub fn unexplained_lasers(&self) -> Vec<LaserTip> {
+        let size = self.hidden.size();
+        let mut lasers = vec![];
+        for (direction, shift, obs) in self.observations.iter() {
+            if obs == NOT_PROBED {
+                continue;
+            }
+            let laser = LaserTip::new(shift, direction, size);
+            let result = self.observations.traverse(laser, &self.hidden);
+            // Letters are shown at both ends, only draw each path once.
+            let seen = match result {
+                TraversalResult::Exited { side, shift } => {
+                    lasers.contains(&LaserTip::new(shift, side, size))
+                }
+                _ => false,
+            };
+            if !seen && !result.looks_like(self.observations.traverse(laser, &self.marks)) {
+                lasers.push(laser);
+            }
+        }
+        lasers
+    }
+
+    /// The final score when submitting the current marks.
+    pub fn score(&self) -> Score {
+        Score {
+            probe_points: self.probe_points(),
+            ..Score::new(&self.observations, self.guess())
+        }
+    }
+}
+
+/// Something the player wants to do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Fire(u8, Direction),
+    Undo,
+    Mark(I8Vec2),
+    Guess,
+    Hint(Strategy),
+    Help,
+    Quit,
+}
+
+const HELP: &str = "Commands:
+  fire <side> <index>   Fire a laser from the left, right, top or bottom side.
+  undo                  Take back the last laser. Its points are still charged.
+  mark <x> <y>          Mark a field as atom, or remove the mark.
+  guess                 Submit your marks and reveal the atoms.
+  hint [worst]          Suggest a laser to fire next. With \"worst\", the suggestion keeps the
+                        number of possible grids small even when the result is unlucky.
+                        Hints need a few lasers first, until at most 1000 grids are possible.
+  help                  Show this help.
+  quit                  Give up.
+";
+
+/// Parses one line of player input. Sides may be abbreviated to their first letter.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |word: &str| {
+        word.parse::<u8>()
+            .map_err(|_| format!("\"{}\" is not a valid number", word))
+    };
+    let coordinate = |word: &str| {
+        i8::try_from(number(word)?).map_err(|_| format!("\"{}\" is not a valid coordinate", word))
+    };
+    match words[..] {
+        ["fire" | "f", side, index] => Ok(Command::Fire(number(index)?, parse_side(side)?)),
+        ["undo" | "u"] => Ok(Command::Undo),
+        ["mark" | "m", x, y] => Ok(Command::Mark(I8Vec2::new(coordinate(x)?, coordinate(y)?))),
+        ["guess" | "g"] => Ok(Command::Guess),
+        ["hint"] => Ok(Command::Hint(Strategy::MaxInformation)),
+        ["hint", "worst"] => Ok(Command::Hint(Strategy::MinWorstCase)),
+        ["help" | "h" | "?"] => Ok(Command::Help),
+        ["quit" | "q"] => Ok(Command::Quit),
+        _ => Err(format!("Unknown command \"{}\", try \"help\"", line.trim())),
+    }
+}
+
+/// The direction of a laser fired from the given side. Sides may be abbreviated to their first
+/// letter.
+pub fn parse_side(side: &str) -> Result<Direction, String> {
+    match side {
+        "left" | "l" => Ok(Right),
+        "right" | "r" => Ok(Left),
+        "top" | "t" => Ok(Down),
+        "bottom" | "b" => Ok(Up),
+        _ => Err(format!("Unknown side \"{}\"", side)),
+    }
+}
+
+/// The side a laser moving in the given direction is fired from, as used by [parse_command].
+pub fn side_name(direction: Direction) -> &'static str {
+    match direction {
+        Right => "left",
+        Left => "right",
+        Down => "top",
+        Up => "bottom",
+    }
+}
+
+/// Draws the observations and the player's marks with row and column numbers.
+pub fn draw_board(game: &Game) -> Result<String, std::fmt::Error> {
+    let size = game.marks.size();
+    let board = observation::draw(&game.marks, &game.observations)?;
+
+    let mut f = String::new();
+    f.write_str("     ")?;
+    for x in 0..size.width() {
+        write!(f, " {}", x % 10)?;
+    }
+    f.write_char('\n')?;
+    for (i, line) in board.lines().enumerate() {
+        if i == 0 || i > size.height() as usize {
+            writeln!(f, "   {}", line)?;
+        } else {
+            writeln!(f, "{:>2} {}", (i - 1) % 100, line)?;
+        }
+    }
+    Ok(f)
+}
+
+/// Runs the game loop until the player submits a guess or quits.
+pub fn run(
+    game: &mut Game,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    let size = game.hidden.size();
+    writeln!(
+        output,
+        "Find the {} atoms hidden in the {}x{} box.",
+        game.atom_count(),
+        size.width(),
+        size.height()
+    )?;
+    output.write_all(HELP.as_bytes())?;
+
+    let mut line = String::new();
+    loop {
+        writeln!(
+            output,
+            "\n{}",
+            draw_board(game).expect("Failed to draw board")
+        )?;
+        write!(
+            output,
+            "{} points, {} of {} atoms marked > ",
+            game.probe_points(),
+            game.mark_count(),
+            game.atom_count()
+        )?;
+        output.flush()?;
+
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        match parse_command(&line) {
+            Ok(Command::Fire(shift, direction)) => {
+                if shift >= size.side_length(direction) {
+                    writeln!(output, "There is no laser {} on that side.", shift)?;
+                } else {
+                    match game.fire(shift, direction) {
+                        Some(obs) => writeln!(
+                            output,
+                            "The laser shows {} and costs {} points.",
+                            obs,
+                            score::probe_cost(obs)
+                        )?,
+                        None => writeln!(output, "You already know what this laser does.")?,
+                    }
+                }
+            }
+            Ok(Command::Undo) => match game.undo() {
+                Some((shift, direction)) => writeln!(
+                    output,
+                    "Took back the laser {} from the {} side.",
+                    shift,
+                    side_name(direction)
+                )?,
+                None => writeln!(output, "There is no laser to take back.")?,
+            },
+            Ok(Command::Mark(v)) => {
+                if v.in_grid(size) {
+                    game.toggle_mark(v);
+                } else {
+                    writeln!(output, "There is no field ({}, {}).", v.x, v.y)?;
+                }
+            }
+            Ok(Command::Guess) => {
+                let result = game.submit();
+                writeln!(
+                    output,
+                    "{}",
+                    observation::draw(&game.hidden, game.observations())
+                        .expect("Failed to draw solution")
+                )?;
+                if result.is_solved() {
+                    writeln!(output, "Solved! You found all {} atoms.", game.atom_count())?;
+                } else {
+                    writeln!(
+                        output,
+                        "{} correct, {} wrong and {} missed atoms.",
+                        result.correct, result.wrong, result.missed
+                    )?;
+                    let lasers = game.unexplained_lasers();
+                    if !lasers.is_empty() {
+                        writeln!(
+                            output,
+                            "\nYour marks do not explain these lasers:\n{}",
+                            observation::draw_paths(&game.hidden, game.observations(), &lasers)
+                                .expect("Failed to draw laser paths")
+                        )?;
+                    }
+                }
+                let score = game.score();
+                writeln!(
+                    output,
+                    "Score: {} ({} for lasers, {} for wrong atoms). Lower is better.",
+                    score.total(),
+                    score.probe_points,
+                    score.penalty_points
+                )?;
+                return Ok(());
+            }
+            Ok(Command::Hint(strategy)) => match game.hint(strategy) {
+                Some((advice, candidates)) => writeln!(
+                    output,
+                    "Try \"fire {} {}\": it tells {:.1} bits, at most {} of {} possible grids remain.",
+                    side_name(advice.direction),
+                    advice.shift,
+                    advice.information,
+                    advice.worst_case_candidates,
+                    candidates
+                )?,
+                None => {
+                    let observations = game.observations().iter();
+                    if observations.iter().all(|&(_, _, obs)| obs != NOT_PROBED) {
+                        writeln!(output, "No hint available, every laser was fired.")?
+                    } else {
+                        writeln!(
+                            output,
+                            "No hint available, more than {} grids are still possible.",
+                            MAX_HINT_CANDIDATES
+                        )?
+                    }
+                }
+            },
+            Ok(Command::Help) => output.write_all(HELP.as_bytes())?,
+            Ok(Command::Quit) => return Ok(()),
+            Err(message) => writeln!(output, "{}", message)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::{GridSize, DEFAULT_SIZE};
+    use crate::generator;
+    use crate::observation::LASER_ABSORBED;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("fire left 3"), Ok(Command::Fire(3, Right)));
+        assert_eq!(parse_command(" f b 0 \n"), Ok(Command::Fire(0, Up)));
+        assert_eq!(
+            parse_command("mark 2 5"),
+            Ok(Command::Mark(I8Vec2::new(2, 5)))
+        );
+        assert_eq!(parse_command("guess"), Ok(Command::Guess));
+        assert_eq!(parse_command("undo"), Ok(Command::Undo));
+        assert_eq!(
+            parse_command("hint worst"),
+            Ok(Command::Hint(Strategy::MinWorstCase))
+        );
+        assert!(parse_command("fire middle 3").is_err());
+        assert!(parse_command("mark 2").is_err());
+        assert!(parse_command("mark -1 2").is_err());
+        assert_eq!(
+            parse_command("mark 200 2"),
+            Err("\"200\" is not a valid coordinate".to_string())
+        );
+        assert!(parse_command("").is_err());
+    }
+
+    #[test]
+    fn test_play_game() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let mut game = Game::new(grid.clone(), Rules::CLASSIC);
+        assert_eq!(game.atom_count(), 5);
+
+        assert_eq!(game.fire(2, Right), Some(LASER_ABSORBED));
+        assert_eq!(game.fire(2, Right), None);
+        assert_eq!(game.observations().iter().len(), 32);
+        assert_eq!(game.fire(0, Down).map(|obs| obs.is_letter()), Some(true));
+        assert_eq!(game.probes(), [(2, Right), (0, Down)]);
+        assert_eq!(game.undo(), Some((0, Down)));
+        assert_eq!(game.probes(), [(2, Right)]);
+        assert_eq!(game.undone(), [(0, Down)]);
+        // The letter was seen on both ends before it was taken back.
+        assert_eq!(game.score().probe_points, 3);
+
+        for v in DEFAULT_SIZE.cells().filter(|&v| grid.get(v)) {
+            game.toggle_mark(v);
+        }
+        game.toggle_mark(I8Vec2::new(0, 0));
+        assert_eq!(
+            game.guess(),
+            GuessResult {
+                correct: 5,
+                wrong: 1,
+                missed: 0
+            }
+        );
+        game.toggle_mark(I8Vec2::new(0, 0));
+        assert!(game.guess().is_solved());
+
+        assert!(game.unexplained_lasers().is_empty());
+        assert_eq!(game.fire(0, Right).map(|obs| obs.is_letter()), Some(true));
+        assert!(game.unexplained_lasers().is_empty());
+        // Without the mark at (3, 4), the laser in row 4 would not be absorbed.
+        game.toggle_mark(I8Vec2::new(3, 4));
+        assert!(game.unexplained_lasers().is_empty());
+        game.fire(4, Right);
+        assert_eq!(
+            game.unexplained_lasers(),
+            [LaserTip::new(4, Right, DEFAULT_SIZE)]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let mut game = Game::new(grid, Rules::CLASSIC);
+        let mut input =
+            "fire left 2\nfire nowhere 1\nfire top 7\nundo\nmark 2 2\nguess\n".as_bytes();
+        let mut output = vec![];
+        run(&mut game, &mut input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        println!("{}", output);
+        assert!(output.contains("Unknown side \"nowhere\""));
+        assert!(output.contains("Took back the laser 7 from the top side."));
+        assert!(output.contains("1 correct, 0 wrong and 4 missed atoms."));
+        // The absorbed laser is explained by the marked atom.
+        assert!(!output.contains("Your marks do not explain"));
+        // The laser taken back is still charged.
+        assert!(output.contains("Score: 22 (2 for lasers, 20 for wrong atoms)."));
+        assert_eq!(game.observations().sides[Right as usize][2], LASER_ABSORBED);
+        assert_eq!(game.undo(), None);
+    }
+
+    #[test]
+    fn test_hint() {
+        let size = GridSize::new(5, 5);
+        let grid = generator::unique_puzzle(size, 3, &mut StdRng::seed_from_u64(14)).unwrap();
+        let mut game = Game::new(grid, Rules::CLASSIC);
+        // All 2300 grids are possible before the first laser.
+        assert_eq!(game.hint(Strategy::MaxInformation), None);
+        let mut input = "hint\nquit\n".as_bytes();
+        let mut output = vec![];
+        run(&mut game, &mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("No hint available, more than 1000 grids are still possible."));
+
+        for shift in 0..2 {
+            game.fire(shift, Right);
+        }
+        let (advice, candidates) = game.hint(Strategy::MaxInformation).unwrap();
+        assert_eq!(
+            candidates,
+            brute_force::find_all_solutions(game.observations(), 3).len()
+        );
+        assert!(advice.information > 0.0);
+
+        let command = format!("fire {} {}", side_name(advice.direction), advice.shift);
+        assert_eq!(
+            parse_command(&command),
+            Ok(Command::Fire(advice.shift, advice.direction))
+        );
+
+        let mut input = "hint\nquit\n".as_bytes();
+        let mut output = vec![];
+        run(&mut game, &mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!("Try \"{}\"", command)));
+    }
+}
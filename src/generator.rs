@@ -0,0 +1,173 @@
+//! Creates puzzles which can be solved without guessing.
+//!
+//! Random grids often hide atoms that no laser can tell apart from other positions, e.g. an atom
+//! surrounded by other atoms. The generator checks every grid with the
+//! [exhaustive solver](crate::brute_force) and moves atoms until only one grid fits the
+//! observations.
+
+use crate::atom_grid::{AtomGrid, GridSize};
+use crate::brute_force;
+use crate::i8vec2::I8Vec2;
+use crate::laser::Rules;
+use crate::observation::{Observations, NOT_PROBED};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// How often an ambiguous grid is repaired before giving up.
+pub const MAX_REPAIRS: usize = 1000;
+
+/// Creates a grid with `atom_count` atoms whose full set of observations has exactly one solution.
+///
+/// Starts with [AtomGrid::random]. While another grid produces the same observations, one of the
+/// atoms the two grids disagree on is moved to a random empty cell. Returns `None` if no uniquely
+/// solvable grid was found after [MAX_REPAIRS] moves, which happens when the grid is so full that
+/// atoms hide each other everywhere.
+pub fn unique_puzzle(size: GridSize, atom_count: u8, rng: &mut impl Rng) -> Option<AtomGrid> {
+    unique_puzzle_with_rules(size, atom_count, Rules::CLASSIC, rng)
+}
+
+/// Like [unique_puzzle], but the lasers follow the given rules.
+pub fn unique_puzzle_with_rules(
+    size: GridSize,
+    atom_count: u8,
+    rules: Rules,
+    rng: &mut impl Rng,
+) -> Option<AtomGrid> {
+    let mut grid = AtomGrid::random(size, atom_count, rng);
+    for _ in 0..=MAX_REPAIRS {
+        let observations = Observations::observe_all_with_rules(&grid, rules);
+        let other = brute_force::find_solutions(&observations, atom_count, 2)
+            .into_iter()
+            .find(|solution| *solution != grid);
+        let Some(other) = other else {
+            return Some(grid);
+        };
+
+        // The other grid has the same number of atoms, so at least one of ours is not part of it.
+        let hidden: Vec<I8Vec2> = size
+            .cells()
+            .filter(|&v| grid.get(v) && !other.get(v))
+            .collect();
+        let empty: Vec<I8Vec2> = size.cells().filter(|&v| !grid.get(v)).collect();
+        if empty.is_empty() {
+            return None;
+        }
+        grid.set(hidden[rng.gen_range(0..hidden.len())], false);
+        grid.set(empty[rng.gen_range(0..empty.len())], true);
+    }
+    None
+}
+
+/// Removes as many observations of the grid as possible while it stays the only solution. Fewer
+/// observations make harder puzzles. The lasers follow the given rules.
+///
+/// Lasers are tried in random order and removed if the puzzle stays uniquely solvable without
+/// them. Once a laser is needed it stays needed, as removing others only makes the puzzle more
+/// ambiguous, so no single laser can be removed from the result. Other orders may lead to smaller
+/// sets, though.
+///
+/// Fewer observations leave more candidate grids for the exhaustive solver, so this is slow for
+/// large grids with many atoms.
+pub fn minimal_observations(grid: &AtomGrid, rules: Rules, rng: &mut impl Rng) -> Observations {
+    let size = grid.size();
+    let atom_count = size.cells().filter(|&v| grid.get(v)).count() as u8;
+    let mut observations = Observations::observe_all_with_rules(grid, rules);
+
+    let mut lasers = observations.iter();
+    lasers.shuffle(rng);
+    for (direction, shift, _) in lasers {
+        if observations.sides[direction as usize][shift as usize] == NOT_PROBED {
+            // The other end of a letter which was already removed.
+            continue;
+        }
+        let mut fewer = observations.clone();
+        fewer.forget(direction, shift);
+        if brute_force::find_solutions(&fewer, atom_count, 2).len() == 1 {
+            observations = fewer;
+        }
+    }
+    observations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::DEFAULT_SIZE;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_unique_puzzle() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for size in [DEFAULT_SIZE, GridSize::new(5, 5), GridSize::new(7, 4)] {
+            for atom_count in 1..=5 {
+                let grid = unique_puzzle(size, atom_count, &mut rng).expect("Puzzle exists");
+                assert_eq!(grid.as_bitboard().count_ones(), atom_count as u32);
+                assert!(is_uniquely_solvable(&grid));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_grid() {
+        // The lower atoms can be arranged differently without changing the observations.
+        let size = GridSize::new(5, 5);
+        let grid = AtomGrid::from_bitboard(size, 1169);
+        assert!(!is_uniquely_solvable(&grid));
+        assert!(is_uniquely_solvable(&AtomGrid::new(size)));
+
+        // There is only one way to fill the grid completely.
+        assert_eq!(
+            unique_puzzle(size, 25, &mut StdRng::seed_from_u64(1)),
+            Some(full(size))
+        );
+    }
+
+    #[test]
+    fn test_minimal_observations() {
+        let mut rng = StdRng::seed_from_u64(13);
+        for size in [GridSize::new(5, 5), GridSize::new(6, 4)] {
+            for atom_count in 1..=4 {
+                let grid = unique_puzzle(size, atom_count, &mut rng).unwrap();
+                let observations = minimal_observations(&grid, Rules::CLASSIC, &mut rng);
+                let full = Observations::observe_all(&grid);
+                let probed = |o: &Observations| {
+                    o.iter()
+                        .iter()
+                        .filter(|(_, _, obs)| *obs != NOT_PROBED)
+                        .count()
+                };
+                assert!(probed(&observations) < probed(&full));
+                assert_eq!(
+                    brute_force::find_all_solutions(&observations, atom_count),
+                    std::slice::from_ref(&grid)
+                );
+
+                // No single laser can be removed anymore.
+                for (direction, shift, obs) in observations.iter() {
+                    if obs != NOT_PROBED {
+                        let mut fewer = observations.clone();
+                        fewer.forget(direction, shift);
+                        assert!(brute_force::find_solutions(&fewer, atom_count, 2).len() > 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks whether the observations of the grid can only be explained by the grid itself.
+    fn is_uniquely_solvable(grid: &AtomGrid) -> bool {
+        let size = grid.size();
+        let atom_count = size.cells().filter(|&v| grid.get(v)).count() as u8;
+        let observations = Observations::observe_all(grid);
+        brute_force::find_solutions(&observations, atom_count, 2) == [grid.clone()]
+    }
+
+    fn full(size: GridSize) -> AtomGrid {
+        let mut grid = AtomGrid::new(size);
+        for v in size.cells() {
+            grid.set(v, true);
+        }
+        grid
+    }
+}
@@ -1,10 +1,12 @@
 //! Simple 2D integer vector based on i8.
 
-use crate::atom_grid::GRID_SIZE;
+use crate::atom_grid::GridSize;
+use rand::Rng;
 use std::ops::{Add, Sub};
 
 /// A simple 2D integer vector based on i8.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct I8Vec2 {
     pub x: i8,
     pub y: i8,
@@ -16,13 +18,14 @@ impl I8Vec2 {
         Self { x, y }
     }
 
-    pub fn in_grid(&self) -> bool {
-        self.x >= 0 && self.x < GRID_SIZE as i8 && self.y >= 0 && self.y < GRID_SIZE as i8
+    pub fn in_grid(&self, size: GridSize) -> bool {
+        size.contains(*self)
     }
 
-    pub fn random() -> Self {
-        let x = rand::random::<usize>() % GRID_SIZE;
-        let y = rand::random::<usize>() % GRID_SIZE;
+    /// A random position inside the grid.
+    pub fn random(size: GridSize, rng: &mut impl Rng) -> Self {
+        let x = rng.gen_range(0..size.width());
+        let y = rng.gen_range(0..size.height());
         Self::new(x as i8, y as i8)
     }
 }
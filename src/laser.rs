@@ -1,5 +1,9 @@
-use crate::atom_grid::{AtomGrid, GRID_SIZE};
+//! How lasers move through the grid.
+
+use crate::atom_grid::{AtomGrid, Cell, GridSize};
 use crate::i8vec2::I8Vec2;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use Direction::*;
 
 /// We simulate the laser moving through the black box step by step. The laser starts at the border
@@ -9,18 +13,60 @@ use Direction::*;
 /// Those should be the only positions that can have any atoms at all. All other positions are
 /// guaranteed to be empty. (Proof of this follows from the assumption that this held previously +
 /// applying the movement rules)
+///
+/// Other [objects](Cell) only matter when the laser moves into their field.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedLaser"))]
 pub struct LaserTip {
     position: I8Vec2,
     direction: Direction,
 }
 
+/// The stored form of a [LaserTip], checked before it becomes one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedLaser {
+    position: I8Vec2,
+    direction: Direction,
+}
+
+/// Only lasers waiting on the border to enter a grid can be stored, like the ones
+/// [LaserTip::new] creates. The size of the grid is not stored, so [Observations] check that the
+/// lasers of their history fit their grid.
+///
+/// [Observations]: crate::observation::Observations
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedLaser> for LaserTip {
+    type Error = String;
+
+    fn try_from(laser: UncheckedLaser) -> Result<Self, String> {
+        let I8Vec2 { x, y } = laser.position;
+        let entering = match laser.direction {
+            Up => x >= 0 && y >= 1,
+            Down => x >= 0 && y == -1,
+            Left => x >= 1 && y >= 0,
+            Right => x == -1 && y >= 0,
+        };
+        if !entering {
+            return Err(format!(
+                "The laser at {:?} moving {:?} does not enter a grid",
+                laser.position, laser.direction
+            ));
+        }
+        Ok(LaserTip {
+            position: laser.position,
+            direction: laser.direction,
+        })
+    }
+}
+
 impl LaserTip {
     // Creating a new laser at the border of the box with a given shift and direction.
-    pub fn new(shift: u8, direction: Direction) -> Self {
+    pub fn new(shift: u8, direction: Direction, size: GridSize) -> Self {
         match direction {
             Up => LaserTip {
-                position: I8Vec2::new(shift as i8, GRID_SIZE as i8),
+                position: I8Vec2::new(shift as i8, size.height() as i8),
                 direction,
             },
             Down => LaserTip {
@@ -28,7 +74,7 @@ impl LaserTip {
                 direction,
             },
             Left => LaserTip {
-                position: I8Vec2::new(GRID_SIZE as i8, shift as i8),
+                position: I8Vec2::new(size.width() as i8, shift as i8),
                 direction,
             },
             Right => LaserTip {
@@ -40,14 +86,14 @@ impl LaserTip {
 
     /// Deconstructs a laser that is on the border of the grid into constructor parameters that
     /// enters the grid from this position.
-    pub fn deconstruct(&self) -> Option<(u8, Direction)> {
+    pub fn deconstruct(&self, size: GridSize) -> Option<(u8, Direction)> {
         if self.position.x == -1 {
             Some((self.position.y as u8, Right))
-        } else if self.position.x == GRID_SIZE as i8 {
+        } else if self.position.x == size.width() as i8 {
             Some((self.position.y as u8, Left))
         } else if self.position.y == -1 {
             Some((self.position.x as u8, Down))
-        } else if self.position.y == GRID_SIZE as i8 {
+        } else if self.position.y == size.height() as i8 {
             Some((self.position.x as u8, Up))
         } else {
             None
@@ -65,88 +111,419 @@ impl LaserTip {
         self.position
     }
 
+    pub fn direction(self) -> Direction {
+        self.direction
+    }
+
     /// Creates a new laser tip following the movement rules on the given atom grid.
     ///
     /// Rule 1: If there is an atom in front, be absorbed.
-    ///  * o *
-    ///  . ↑ .
-    ///  . . .
+    /// ```text
+    /// * o *
+    /// . ↑ .
+    /// . . .
+    /// ```
     ///
     /// Rule 2: If there are no Atoms, move forward.
-    ///  . ↑ .
-    ///  . ↑ .
-    ///  . . .
+    /// ```text
+    /// . ↑ .
+    /// . ↑ .
+    /// . . .
+    /// ```
     ///
     /// Rule 3: If there are two atoms in front (both corners), be reflected.
-    ///  o . o
-    ///  . ↑ .
-    ///  . ↓ .
+    /// ```text
+    /// o . o
+    /// . ↑ .
+    /// . ↓ .
+    /// ```
     ///
     /// Rule 4+5: If there is an atom on the corner, turn and move to the side
-    ///  o . .
-    ///  . ↑ →
-    ///  . . .
-    pub fn move_once(self, grid: &AtomGrid) -> Option<Self> {
+    /// ```text
+    /// o . .
+    /// . ↑ →
+    /// . . .
+    /// ```
+    ///
+    /// The [Rules] may change rule 1: without absorption, an atom in front reflects like rule 3.
+    ///
+    /// Other objects in front take precedence over the atom rules: absorbers absorb, reflectors
+    /// reflect, mirrors turn the laser on their field and portals move it to their other end.
+    pub fn move_once(self, grid: &AtomGrid, rules: &Rules) -> Option<Self> {
+        self.step_in_grid(grid, rules, false).1
+    }
+
+    /// Like [LaserTip::move_once], but asks `is_atom` for the three positions in front instead of
+    /// looking them up in a grid. Positions outside the grid must be reported as empty.
+    pub fn move_with(self, rules: &Rules, is_atom: impl Fn(I8Vec2) -> bool) -> Option<Self> {
+        self.step_with(rules, is_atom).1
+    }
+
+    /// Like [LaserTip::move_with], but also tells which rule was applied.
+    pub fn step_with(
+        self,
+        rules: &Rules,
+        is_atom: impl Fn(I8Vec2) -> bool,
+    ) -> (MoveRule, Option<Self>) {
         // Rule 1. Afterward we can assume front == false.
         let front = self.position + self.direction.dxy();
-        if grid.get(front) {
-            return None;
+        if is_atom(front) {
+            if rules.absorption {
+                return (MoveRule::Absorbed, None);
+            }
+            return (
+                MoveRule::Reflected,
+                Some(LaserTip {
+                    position: self.position + self.direction.flip().dxy(),
+                    direction: self.direction.flip(),
+                }),
+            );
         }
-        let left = grid.get(front + self.direction.counter_clockwise().dxy());
-        let right = grid.get(front + self.direction.clockwise().dxy());
+        let left = is_atom(front + self.direction.counter_clockwise().dxy());
+        let right = is_atom(front + self.direction.clockwise().dxy());
 
         // Rule 2.
         if !left && !right {
-            return Some(LaserTip {
-                position: front,
-                direction: self.direction,
-            });
+            return (
+                MoveRule::Straight,
+                Some(LaserTip {
+                    position: front,
+                    direction: self.direction,
+                }),
+            );
         }
 
         // Rule 3.
         if left && right {
-            return Some(LaserTip {
-                position: self.position + self.direction.flip().dxy(),
-                direction: self.direction.flip(),
-            });
+            return (
+                MoveRule::Reflected,
+                Some(LaserTip {
+                    position: self.position + self.direction.flip().dxy(),
+                    direction: self.direction.flip(),
+                }),
+            );
         }
 
         // Rule 4.
         if left {
-            return Some(LaserTip {
-                position: self.position + self.direction.clockwise().dxy(),
-                direction: self.direction.clockwise(),
-            });
+            return (
+                MoveRule::DeflectedRight,
+                Some(LaserTip {
+                    position: self.position + self.direction.clockwise().dxy(),
+                    direction: self.direction.clockwise(),
+                }),
+            );
         }
         if right {
-            return Some(LaserTip {
-                position: self.position + self.direction.counter_clockwise().dxy(),
-                direction: self.direction.counter_clockwise(),
-            });
+            return (
+                MoveRule::DeflectedLeft,
+                Some(LaserTip {
+                    position: self.position + self.direction.counter_clockwise().dxy(),
+                    direction: self.direction.counter_clockwise(),
+                }),
+            );
         }
         unreachable!("Logic error in laser movement. Movement rules not fully defined.")
     }
 
-    pub fn traverse_grid(self, grid: &AtomGrid) -> (Option<Self>, u8) {
-        let mut laser = self;
-        for move_count in 1..=u8::MAX {
-            let l = laser.move_once(grid);
+    /// Moves the laser inside the grid, see [LaserTip::move_once]. Without edge reflection, only
+    /// the field in front counts when entering the grid.
+    ///
+    /// Atoms turning the laser move it sideways or back onto a field it did not look at yet. If an
+    /// object is there, the laser only turns, so the object acts on it in the next step.
+    fn step_in_grid(
+        self,
+        grid: &AtomGrid,
+        rules: &Rules,
+        entering: bool,
+    ) -> (MoveRule, Option<Self>) {
+        if let Some(step) = self.step_into_object(grid) {
+            return step;
+        }
 
-            if let Some(l) = l {
-                if !l.position.in_grid() {
-                    return (Some(l), move_count);
-                } else {
-                    laser = l;
+        let front = self.forward().position;
+        let (rule, next) = if entering && !rules.edge_reflection {
+            self.step_with(rules, |v| v == front && grid.get(v))
+        } else {
+            self.step_with(rules, |v| grid.get(v))
+        };
+        match next {
+            Some(next) if next.position != front => {
+                let turned = LaserTip {
+                    position: self.position,
+                    direction: next.direction,
+                };
+                if turned.step_into_object(grid).is_some() {
+                    return (rule, Some(turned));
                 }
+                (rule, Some(next))
+            }
+            _ => (rule, next),
+        }
+    }
+
+    /// Moves the laser onto the field in front if an object there acts on it. Returns `None` for
+    /// empty fields and atoms.
+    fn step_into_object(self, grid: &AtomGrid) -> Option<(MoveRule, Option<Self>)> {
+        let front = self.forward().position;
+        match grid.cell(front) {
+            Cell::Empty | Cell::Atom => None,
+            Cell::Absorber => Some((MoveRule::Absorbed, None)),
+            Cell::Reflector => Some((
+                MoveRule::Reflected,
+                Some(LaserTip {
+                    position: self.position + self.direction.flip().dxy(),
+                    direction: self.direction.flip(),
+                }),
+            )),
+            Cell::Mirror { rising } => {
+                let direction = match (rising, self.direction) {
+                    (true, Up) | (false, Down) => Right,
+                    (true, Right) | (false, Left) => Up,
+                    (true, Down) | (false, Up) => Left,
+                    (true, Left) | (false, Right) => Down,
+                };
+                let rule = if direction == self.direction.counter_clockwise() {
+                    MoveRule::DeflectedLeft
+                } else {
+                    MoveRule::DeflectedRight
+                };
+                Some((
+                    rule,
+                    Some(LaserTip {
+                        position: front,
+                        direction,
+                    }),
+                ))
+            }
+            // A portal without its other end is an empty field.
+            Cell::Portal(_) => grid.portal_exit(front).map(|exit| {
+                (
+                    MoveRule::Teleported,
+                    Some(LaserTip {
+                        position: exit,
+                        direction: self.direction,
+                    }),
+                )
+            }),
+        }
+    }
+
+    /// Marks the laser's position and direction as visited. Returns false if they were visited
+    /// before. `visited` has an entry for each direction on each field of the grid, lasers outside
+    /// the grid are never visited twice.
+    fn first_visit(self, visited: &mut [bool], size: GridSize) -> bool {
+        if !self.position.in_grid(size) {
+            return true;
+        }
+        let index = 4 * size.index(self.position) + self.direction as usize;
+        !std::mem::replace(&mut visited[index], true)
+    }
+
+    /// Fires the laser from the border through the grid and tells how it ends up. The laser is
+    /// followed until it is absorbed, leaves the grid or comes back to a position it already
+    /// passed in the same direction.
+    pub fn traverse_grid(self, grid: &AtomGrid, rules: &Rules) -> TraversalResult {
+        let size = grid.size();
+        let entry = self
+            .deconstruct(size)
+            .expect("Lasers are fired from the border");
+        let mut visited = vec![false; 4 * size.cell_count()];
+        let mut laser = self;
+        let mut entering = true;
+        loop {
+            if !laser.first_visit(&mut visited, size) {
+                // The laser repeats the moves since its last visit forever.
+                return TraversalResult::Looped;
+            }
+            let next = if entering {
+                laser.step_in_grid(grid, rules, true).1
             } else {
-                return (None, move_count);
+                laser.move_once(grid, rules)
+            };
+
+            match next {
+                Some(l) if !l.position.in_grid(size) => {
+                    // Deflected right at the border, this counts as a reflection. Without edge
+                    // reflection, the laser can only have been reflected by an atom right in front.
+                    if entering {
+                        return TraversalResult::Reflected;
+                    }
+                    let (shift, side) = l
+                        .deconstruct(size)
+                        .expect("Lasers leave the grid across the border");
+                    if (shift, side) == entry {
+                        return TraversalResult::Reflected;
+                    }
+                    return TraversalResult::Exited { side, shift };
+                }
+                Some(l) => laser = l,
+                None => {
+                    return TraversalResult::Absorbed {
+                        at: laser.forward().position,
+                    }
+                }
+            }
+            entering = false;
+        }
+    }
+
+    /// Like [LaserTip::traverse_grid], but records every step on the way. The last step either
+    /// absorbs the laser, leaves the grid or leads back to the start of a loop.
+    pub fn trace(self, grid: &AtomGrid, rules: &Rules) -> Vec<Step> {
+        let mut visited = vec![false; 4 * grid.size().cell_count()];
+        let mut steps = vec![];
+        let mut laser = self;
+        while laser.first_visit(&mut visited, grid.size()) {
+            let (rule, next) = laser.step_in_grid(grid, rules, steps.is_empty());
+            steps.push(Step {
+                from: laser,
+                rule,
+                to: next,
+            });
+            match next {
+                Some(l) if l.position.in_grid(grid.size()) => laser = l,
+                _ => break,
+            }
+        }
+        steps
+    }
+}
+
+/// How a laser fired into the grid ends up, see [LaserTip::traverse_grid].
+#[derive(Copy, Clone, PartialEq, Debug, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraversalResult {
+    /// The laser was absorbed by the atom or absorber at the given field.
+    Absorbed { at: I8Vec2 },
+    /// The laser came back out where it entered.
+    Reflected,
+    /// The laser came out somewhere else. The side is given as the direction of a laser fired
+    /// from there, like the index of [Observations::sides](crate::observation::Observations).
+    Exited { side: Direction, shift: u8 },
+    /// The laser never leaves the grid. This is impossible with the classic rules, but lasers may
+    /// get trapped with other [Rules] or [objects](Cell).
+    Looped,
+}
+
+impl TraversalResult {
+    /// Whether the results look the same from outside the grid, where it can not be seen where a
+    /// laser was absorbed.
+    pub fn looks_like(self, other: TraversalResult) -> bool {
+        match (self, other) {
+            (TraversalResult::Absorbed { .. }, TraversalResult::Absorbed { .. }) => true,
+            _ => self == other,
+        }
+    }
+}
+
+/// The physics of the lasers, see [LaserTip::move_once]. The default are the classic Black Box
+/// rules.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rules {
+    /// A laser with an atom on a corner right at the border is deflected back out immediately,
+    /// which counts as reflection. Without, atoms on the corners are ignored when entering, and a
+    /// laser may get trapped between atoms on opposite borders.
+    pub edge_reflection: bool,
+    /// An atom right in front absorbs the laser. Without, the laser is reflected.
+    pub absorption: bool,
+}
+
+impl Rules {
+    pub const CLASSIC: Rules = Rules {
+        edge_reflection: true,
+        absorption: true,
+    };
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules::CLASSIC
+    }
+}
+
+/// Rules are written as "classic" or a comma separated list of the changes to the classic rules,
+/// "no-edge-reflection" and "no-absorption".
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut rules = Rules::CLASSIC;
+        if s == "classic" {
+            return Ok(rules);
+        }
+        for change in s.split(',') {
+            match change {
+                "no-edge-reflection" => rules.edge_reflection = false,
+                "no-absorption" => rules.absorption = false,
+                _ => return Err(format!("Unknown rules \"{}\"", change)),
             }
         }
-        panic!("Laser did not leave the grid after 255 moves. Infinite loop detected.");
+        Ok(rules)
     }
 }
 
+/// Writes the rules in the format [Rules::from_str] reads.
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut changes = vec![];
+        if !self.edge_reflection {
+            changes.push("no-edge-reflection");
+        }
+        if !self.absorption {
+            changes.push("no-absorption");
+        }
+        if changes.is_empty() {
+            f.write_str("classic")
+        } else {
+            f.write_str(&changes.join(","))
+        }
+    }
+}
+
+/// The movement rule applied in a single step, see [LaserTip::move_once]. Left and right are seen
+/// from the laser.
 #[derive(Copy, Clone, PartialEq, Debug, Eq)]
+pub enum MoveRule {
+    /// Rule 1: An atom in front.
+    Absorbed,
+    /// Rule 2: No atoms.
+    Straight,
+    /// Rule 3: Atoms on both corners in front.
+    Reflected,
+    /// Rule 4+5: An atom on the right corner turns the laser to the left.
+    DeflectedLeft,
+    /// Rule 4+5: An atom on the left corner turns the laser to the right.
+    DeflectedRight,
+    /// The laser moved into a portal and continues from its other end.
+    Teleported,
+}
+
+impl Display for MoveRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MoveRule::Absorbed => "absorbed",
+            MoveRule::Straight => "straight",
+            MoveRule::Reflected => "reflected",
+            MoveRule::DeflectedLeft => "deflected left",
+            MoveRule::DeflectedRight => "deflected right",
+            MoveRule::Teleported => "teleported",
+        })
+    }
+}
+
+/// A single step of a laser's path.
+#[derive(Copy, Clone, PartialEq, Debug, Eq)]
+pub struct Step {
+    pub from: LaserTip,
+    pub rule: MoveRule,
+    /// `None` if the laser was absorbed.
+    pub to: Option<LaserTip>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Direction {
     Up = 0,
     Down = 1,
@@ -199,84 +576,275 @@ impl Direction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::atom_grid::DEFAULT_SIZE;
 
     #[test]
     fn test_deconstruction() {
-        for i in 0..GRID_SIZE {
-            assert_eq!(
-                LaserTip::new(i as u8, Up).deconstruct(),
-                Some((i as u8, Up))
-            );
-            let mut l = LaserTip::new(i as u8, Up);
-            l.direction = l.direction.flip();
-            assert_eq!(l.deconstruct(), Some((i as u8, Up)));
-            assert_eq!(
-                LaserTip::new(i as u8, Down).deconstruct(),
-                Some((i as u8, Down))
-            );
-            assert_eq!(
-                LaserTip::new(i as u8, Left).deconstruct(),
-                Some((i as u8, Left))
-            );
-            assert_eq!(
-                LaserTip::new(i as u8, Right).deconstruct(),
-                Some((i as u8, Right))
-            );
+        for size in [DEFAULT_SIZE, GridSize::new(5, 7), GridSize::new(10, 3)] {
+            for direction in Direction::all() {
+                for i in 0..size.side_length(direction) {
+                    let l = LaserTip::new(i, direction, size);
+                    assert!(!l.position.in_grid(size));
+                    assert!(l.forward().position.in_grid(size));
+                    assert_eq!(l.deconstruct(size), Some((i, direction)));
+                }
+            }
+            for i in 0..size.width() {
+                let mut l = LaserTip::new(i, Up, size);
+                l.direction = l.direction.flip();
+                assert_eq!(l.deconstruct(size), Some((i, Up)));
+            }
         }
     }
 
     #[test]
     fn test_laser_path() {
-        let grid = AtomGrid::from_bitboard(35184640598018);
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
 
         println!("{}", grid);
 
         // Watch it move a few steps
-        let laser = LaserTip::new(0, Right);
+        let laser = LaserTip::new(0, Right, DEFAULT_SIZE);
         assert_eq!(laser.position, I8Vec2::new(-1, 0));
-        let laser = laser.move_once(&grid).expect("moving possible");
+        let laser = laser
+            .move_once(&grid, &Rules::CLASSIC)
+            .expect("moving possible");
         assert_eq!(laser.position, I8Vec2::new(0, 0));
-        let laser = laser.move_once(&grid).expect("moving possible");
+        let laser = laser
+            .move_once(&grid, &Rules::CLASSIC)
+            .expect("moving possible");
         assert_eq!(laser.position, I8Vec2::new(1, 0));
 
         // Restart the laser and let it traverse the grid
-        let laser = LaserTip::new(0, Right);
-        let laser = laser.traverse_grid(&grid).0.expect("traversal possible");
-        assert_eq!(laser.position, I8Vec2::new(8, 0));
+        let traverse =
+            |shift| LaserTip::new(shift, Right, DEFAULT_SIZE).traverse_grid(&grid, &Rules::CLASSIC);
+        let exited = |side, shift| TraversalResult::Exited { side, shift };
+        let absorbed = |x, y| TraversalResult::Absorbed {
+            at: I8Vec2::new(x, y),
+        };
+        assert_eq!(traverse(0), exited(Left, 0));
 
-        // When shining the laser in the second row, we expect a reflection towards the top
-        let laser = LaserTip::new(1, Right);
-        let laser = laser.traverse_grid(&grid).0.expect("traversal possible");
-        assert_eq!(laser.position, I8Vec2::new(1, -1));
+        // When shining the laser in the second row, we expect a deflection towards the top
+        assert_eq!(traverse(1), exited(Down, 1));
 
         // Next laser is absorbed
-        let laser = LaserTip::new(2, Right);
-        let laser = laser.traverse_grid(&grid);
-        assert_eq!(laser, (None, 3));
+        assert_eq!(traverse(2), absorbed(2, 2));
 
-        // Next laser leaves the grid on the left side, but further down. It was reflected twice.
-        let laser = LaserTip::new(3, Right);
-        let laser = laser.traverse_grid(&grid).0.expect("traversal possible");
-        assert_eq!(laser.position, I8Vec2::new(-1, 5));
+        // Next laser leaves the grid on the left side, but further down. It was deflected twice.
+        assert_eq!(traverse(3), exited(Right, 5));
 
         // On y=4 the laser is absorbed again
-        let laser = LaserTip::new(4, Right);
-        let laser = laser.traverse_grid(&grid);
-        assert_eq!(laser, (None, 4));
+        assert_eq!(traverse(4), absorbed(3, 4));
 
         // For y=5 the laser comes back to y=3 by symmetry
-        let laser = LaserTip::new(5, Right);
-        let laser = laser.traverse_grid(&grid).0.expect("traversal possible");
-        assert_eq!(laser.position, I8Vec2::new(-1, 3));
+        assert_eq!(traverse(5), exited(Right, 3));
 
         // For y=6 the laser is absorbed again
-        let laser = LaserTip::new(6, Right);
-        let laser = laser.traverse_grid(&grid);
-        assert_eq!(laser, (None, 3));
-
-        // For y=7 the laser is reflected down.
-        let laser = LaserTip::new(7, Right);
-        let laser = laser.traverse_grid(&grid).0.expect("traversal possible");
-        assert_eq!(laser.position, I8Vec2::new(1, 8));
+        assert_eq!(traverse(6), absorbed(2, 6));
+
+        // For y=7 the laser is deflected down.
+        assert_eq!(traverse(7), exited(Up, 1));
+    }
+
+    #[test]
+    fn test_trace() {
+        use MoveRule::*;
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        for direction in Direction::all() {
+            for shift in 0..DEFAULT_SIZE.side_length(direction) {
+                let laser = LaserTip::new(shift, direction, DEFAULT_SIZE);
+                let steps = laser.trace(&grid, &Rules::CLASSIC);
+                let last = steps.last().unwrap();
+                let exit = last.to.and_then(|to| to.deconstruct(DEFAULT_SIZE));
+                match laser.traverse_grid(&grid, &Rules::CLASSIC) {
+                    TraversalResult::Absorbed { at } => {
+                        assert_eq!(last.to, None);
+                        assert_eq!(last.from.forward().position, at);
+                    }
+                    TraversalResult::Reflected => {
+                        assert!(steps.len() == 1 || exit == Some((shift, direction)))
+                    }
+                    TraversalResult::Exited { side, shift } => {
+                        assert_eq!(exit, Some((shift, side)))
+                    }
+                    TraversalResult::Looped => unreachable!("Impossible with the classic rules"),
+                }
+                assert_eq!(steps[0].from, laser);
+                for pair in steps.windows(2) {
+                    assert_eq!(pair[0].to, Some(pair[1].from));
+                }
+            }
+        }
+
+        // In the second row, the atom at (2, 2) deflects the laser towards the top.
+        let rules = |shift| {
+            let steps = LaserTip::new(shift, Right, DEFAULT_SIZE).trace(&grid, &Rules::CLASSIC);
+            steps.iter().map(|step| step.rule).collect::<Vec<_>>()
+        };
+        assert_eq!(rules(1), [Straight, Straight, DeflectedLeft, Straight]);
+
+        // The laser in the fourth row turns right twice and comes back further down.
+        assert_eq!(
+            rules(3),
+            [
+                Straight,
+                Straight,
+                DeflectedRight,
+                Straight,
+                DeflectedRight,
+                Straight
+            ]
+        );
+        let steps = LaserTip::new(2, Right, DEFAULT_SIZE).trace(&grid, &Rules::CLASSIC);
+        assert_eq!(steps.last().unwrap().rule, MoveRule::Absorbed);
+        assert_eq!(steps.last().unwrap().to, None);
+    }
+
+    #[test]
+    fn test_objects() {
+        let grid: AtomGrid = " . * . .\n . . / .\n # . . 1\n 1 . o .\n".parse().unwrap();
+        let size = grid.size();
+        let traverse = |shift, direction| {
+            LaserTip::new(shift, direction, size).traverse_grid(&grid, &Rules::CLASSIC)
+        };
+        let exited = |side, shift| TraversalResult::Exited { side, shift };
+        // The absorber only stops lasers running into it, the reflector sends them back.
+        assert_eq!(
+            traverse(1, Down),
+            TraversalResult::Absorbed {
+                at: I8Vec2::new(1, 0)
+            }
+        );
+        assert_eq!(traverse(0, Down), TraversalResult::Reflected);
+        // The mirror turns lasers coming from the left up and lasers coming from the top left.
+        assert_eq!(traverse(1, Right), exited(Down, 2));
+        assert_eq!(traverse(2, Down), exited(Right, 1));
+        // Entering a portal continues from the other one in the same direction.
+        assert_eq!(traverse(0, Up), exited(Down, 3));
+        assert_eq!(traverse(2, Left), exited(Right, 3));
+
+        // Objects on the field a laser is deflected onto still act on it.
+        let deflected = |text: &str| {
+            let grid: AtomGrid = text.parse().unwrap();
+            LaserTip::new(1, Right, grid.size()).traverse_grid(&grid, &Rules::CLASSIC)
+        };
+        assert_eq!(
+            deflected(" . . o\n . . .\n . * ."),
+            TraversalResult::Absorbed {
+                at: I8Vec2::new(1, 2)
+            }
+        );
+        assert_eq!(deflected(" 1 . o\n . . .\n . 1 ."), exited(Up, 0));
+        assert_eq!(deflected(" . . o\n . . .\n . / ."), exited(Right, 2));
+
+        // Without its other end, a portal is an empty field.
+        let mut single = grid.clone();
+        single.set_cell(I8Vec2::new(3, 2), Cell::Empty).unwrap();
+        let laser = LaserTip::new(0, Up, size);
+        assert_eq!(
+            laser.traverse_grid(&single, &Rules::CLASSIC),
+            TraversalResult::Reflected
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!("classic".parse(), Ok(Rules::CLASSIC));
+        assert_eq!(
+            "no-absorption,no-edge-reflection".parse(),
+            Ok(Rules {
+                edge_reflection: false,
+                absorption: false
+            })
+        );
+        assert!("no-gravity".parse::<Rules>().is_err());
+        for text in [
+            "classic",
+            "no-edge-reflection,no-absorption",
+            "no-absorption",
+        ] {
+            assert_eq!(text.parse::<Rules>().unwrap().to_string(), text);
+        }
+
+        let size = GridSize::square(5);
+        let mut grid = AtomGrid::new(size);
+        grid.set(I8Vec2::new(2, 0), true);
+
+        // Without absorption, the atom in front sends the laser back the way it came.
+        let laser = LaserTip::new(0, Right, size);
+        let no_absorption = "no-absorption".parse().unwrap();
+        assert_eq!(
+            laser.traverse_grid(&grid, &Rules::CLASSIC),
+            TraversalResult::Absorbed {
+                at: I8Vec2::new(2, 0)
+            }
+        );
+        assert_eq!(
+            laser.traverse_grid(&grid, &no_absorption),
+            TraversalResult::Reflected
+        );
+
+        // Without edge reflection, the atom on the corner of the entry is ignored and the laser
+        // passes straight through.
+        let laser = LaserTip::new(1, Down, size);
+        let no_edge_reflection = "no-edge-reflection".parse().unwrap();
+        assert_eq!(
+            laser.traverse_grid(&grid, &Rules::CLASSIC),
+            TraversalResult::Reflected
+        );
+        assert_eq!(
+            laser.traverse_grid(&grid, &no_edge_reflection),
+            TraversalResult::Exited { side: Up, shift: 1 }
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        // Without edge reflection, the laser bounces between the atom pairs on the top and bottom.
+        let grid: AtomGrid = " o . o\n . . .\n . . .\n o . o\n".parse().unwrap();
+        let laser = LaserTip::new(1, Up, grid.size());
+        let rules = "no-edge-reflection".parse().unwrap();
+        assert_eq!(laser.traverse_grid(&grid, &rules), TraversalResult::Looped);
+        let steps = laser.trace(&grid, &rules);
+        let last = steps.last().unwrap();
+        assert!(steps.iter().any(|step| Some(step.from) == last.to));
+
+        // With the classic rules, the laser is reflected right at the border.
+        assert_eq!(
+            laser.traverse_grid(&grid, &Rules::CLASSIC),
+            TraversalResult::Reflected
+        );
+    }
+
+    #[test]
+    fn test_looks_like() {
+        let absorbed = |x| TraversalResult::Absorbed {
+            at: I8Vec2::new(x, 0),
+        };
+        assert!(absorbed(0).looks_like(absorbed(2)));
+        assert!(TraversalResult::Looped.looks_like(TraversalResult::Looped));
+        assert!(!TraversalResult::Looped.looks_like(TraversalResult::Reflected));
+        assert!(!absorbed(0).looks_like(TraversalResult::Reflected));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let laser = LaserTip::new(2, Left, GridSize::new(5, 4));
+        let json = serde_json::to_string(&laser).unwrap();
+        assert_eq!(json, r#"{"position":{"x":5,"y":2},"direction":"Left"}"#);
+        assert_eq!(serde_json::from_str::<LaserTip>(&json).unwrap(), laser);
+        for outside in [
+            r#"{"position":{"x":-1,"y":2},"direction":"Left"}"#,
+            r#"{"position":{"x":0,"y":2},"direction":"Left"}"#,
+            r#"{"position":{"x":2,"y":2},"direction":"Right"}"#,
+            r#"{"position":{"x":-1,"y":-1},"direction":"Down"}"#,
+        ] {
+            assert!(serde_json::from_str::<LaserTip>(outside).is_err());
+        }
+
+        let rules: Rules = "no-absorption".parse().unwrap();
+        let json = serde_json::to_string(&rules).unwrap();
+        assert_eq!(serde_json::from_str::<Rules>(&json).unwrap(), rules);
     }
 }
@@ -0,0 +1,47 @@
+//! A logic puzzle in the style of the board game Black Box: atoms are hidden in a grid and have
+//! to be found by firing lasers into it and watching where they come out.
+//!
+//! The main types are re-exported at the top level:
+//!
+//! - [AtomGrid] is the hidden grid, see [GridSize] for its dimensions.
+//! - [LaserTip] moves a laser through the grid following the [Rules].
+//! - [Observations] collect what the player saw at the border after probing.
+//! - [Solver] derives what can be known about the grid from the observations, the
+//!   [brute_force] module lists all grids that fit them.
+//!
+//! With the `serde` feature, grids, lasers, observations and the solver's knowledge can be
+//! serialized, e.g. to store games or exchange puzzles as JSON.
+//!
+//! ```
+//! use laser_puzzle::{AtomGrid, GridKnowledge, GridSize, I8Vec2, Observations, Solver};
+//!
+//! let mut grid = AtomGrid::new(GridSize::square(5));
+//! grid.set(I8Vec2::new(2, 2), true);
+//! let observations = Observations::observe_all(&grid);
+//!
+//! let knowledge = Solver::default().solve(&observations).unwrap();
+//! assert_eq!(knowledge.get(I8Vec2::new(0, 0)), GridKnowledge::Empty);
+//! assert_eq!(
+//!     laser_puzzle::brute_force::find_all_solutions(&observations, 1),
+//!     [grid]
+//! );
+//! ```
+
+pub mod advisor;
+pub mod atom_grid;
+pub mod brute_force;
+pub mod difficulty;
+pub mod game;
+pub mod generator;
+pub mod i8vec2;
+pub mod laser;
+pub mod observation;
+pub mod score;
+pub mod session;
+pub mod solver;
+
+pub use atom_grid::{AtomGrid, Cell, GridSize};
+pub use i8vec2::I8Vec2;
+pub use laser::{Direction, LaserTip, Rules, TraversalResult};
+pub use observation::{Observation, Observations};
+pub use solver::{Contradiction, GridKnowledge, Solver, UncertainGrid};
@@ -1,26 +1,27 @@
-use crate::atom_grid::AtomGrid;
-use crate::observation::Observations;
+use std::process::ExitCode;
 
-mod atom_grid;
-mod i8vec2;
-mod laser;
-mod observation;
-mod solver;
+mod cli;
 
-fn main() {
-    let g = AtomGrid::random(5);
+fn main() -> ExitCode {
+    let (subcommand, options) = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
 
-    println!("BitBoard: {}", g.as_bitboard());
-
-    let o = Observations::observe_all(&g);
-    println!(
-        "{}",
-        observation::draw(&g, &o).expect("Failed to draw observation")
-    );
-
-    let s = solver::solve_as_much_as_you_can(&o);
-    println!(
-        "{}",
-        solver::draw(&s, &o).expect("Failed to draw solver state")
-    );
+    match cli::run(
+        subcommand,
+        &options,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout(),
+    ) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
 }
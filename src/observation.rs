@@ -1,36 +1,91 @@
-use crate::atom_grid::{AtomGrid, GRID_SIZE};
+//! What the player sees at the border of the grid after firing lasers.
+
+use crate::atom_grid::{AtomGrid, Cell, GridSize, DEFAULT_SIZE};
 use crate::i8vec2::I8Vec2;
 use crate::laser::Direction::*;
-use crate::laser::{Direction, LaserTip};
+use crate::laser::{Direction, LaserTip, MoveRule, Rules, TraversalResult};
+use std::error::Error;
 use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
 
 /// The observation is the information derived from an atom grid using a laser and available to the
 /// player. It is the player's job to use this information to determine the atom grid.
 ///
-/// We store all the observations in a single struct and add to it after each probe.
-#[derive(Debug)]
+/// We store all the observations in a single struct and add to it after each probe. The probes
+/// are kept in the order they were made, so the last one can be undone.
+///
+/// The side a laser is shot from is indexed by the direction the laser is moving in, so
+/// `sides[Right as usize]` is the left border. Each side has one entry per row or column.
+#[derive(Clone, Debug, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "SerializedObservations", try_from = "SerializedObservations")
+)]
 pub struct Observations {
+    size: GridSize,
+    rules: Rules,
     next_observation: Observation,
-    pub sides: [[Observation; GRID_SIZE]; 4],
+    pub(crate) sides: [Vec<Observation>; 4],
+    history: Vec<Probe>,
+}
+
+/// A laser fired by [Observations::probe].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Probe {
+    /// The laser on the border before entering the grid.
+    pub laser: LaserTip,
+    pub result: TraversalResult,
+    /// What was recorded where the laser entered. A letter is recorded at the exit as well.
+    pub observation: Observation,
+}
+
+/// Observations are equal if they show the same on the border, no matter in which order the
+/// lasers were fired.
+impl PartialEq for Observations {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.rules == other.rules
+            && self.next_observation == other.next_observation
+            && self.sides == other.sides
+    }
 }
 
 impl Default for Observations {
     fn default() -> Self {
+        Observations::new(DEFAULT_SIZE)
+    }
+}
+
+impl Observations {
+    /// Creates an empty set of observations for a grid of the given size.
+    pub fn new(size: GridSize) -> Self {
+        Observations::with_rules(size, Rules::CLASSIC)
+    }
+
+    /// Creates an empty set of observations for lasers following the given rules.
+    pub fn with_rules(size: GridSize, rules: Rules) -> Self {
         Observations {
+            size,
+            rules,
             next_observation: Observation(3), // We start at 3 as 0-2 have special significance.
-            sides: [[NOT_PROBED; GRID_SIZE]; 4],
+            sides: Direction::all().map(|d| vec![NOT_PROBED; size.side_length(d) as usize]),
+            history: vec![],
         }
     }
-}
 
-impl Observations {
     pub fn observe_all(grid: &AtomGrid) -> Self {
-        let mut this = Observations::default();
+        Observations::observe_all_with_rules(grid, Rules::CLASSIC)
+    }
+
+    pub fn observe_all_with_rules(grid: &AtomGrid, rules: Rules) -> Self {
+        let mut this = Observations::with_rules(grid.size(), rules);
 
         for direction in Direction::all() {
-            for shift in 0..GRID_SIZE {
+            for shift in 0..grid.size().side_length(direction) as usize {
                 if this.sides[direction as usize][shift] == NOT_PROBED {
-                    this.probe(LaserTip::new(shift as u8, direction), grid);
+                    this.probe(LaserTip::new(shift as u8, direction, grid.size()), grid);
                 }
             }
         }
@@ -38,39 +93,177 @@ impl Observations {
         this
     }
 
-    fn probe(&mut self, laser: LaserTip, grid: &AtomGrid) {
+    pub fn size(&self) -> GridSize {
+        self.size
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Interprets the observations with other rules, e.g. after reading them from text which does
+    /// not record the rules.
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    /// Shoots a laser from the border into the grid and records what happened. Returns the
+    /// observation made at the entry position, or `None` without recording anything if something
+    /// was observed already where the laser enters or comes out.
+    pub fn probe(&mut self, laser: LaserTip, grid: &AtomGrid) -> Option<Observation> {
         let (in_shift, in_direction) = laser
-            .deconstruct()
+            .deconstruct(self.size)
+            .expect("Probing should only happen with side-lasers.");
+        if self.get(in_direction, in_shift) != NOT_PROBED {
+            return None;
+        }
+
+        let result = self.traverse(laser, grid);
+        let observation = match result {
+            TraversalResult::Absorbed { .. } => LASER_ABSORBED,
+            TraversalResult::Looped => LASER_LOOPED,
+            TraversalResult::Reflected => LASER_REFLECTED,
+            TraversalResult::Exited {
+                side: out_direction,
+                shift: out_shift,
+            } => {
+                // Laser came out somewhere else
+                if self.get(out_direction, out_shift) != NOT_PROBED {
+                    return None;
+                }
+                let letter = self.free_letter()?;
+                self.sides[out_direction as usize][out_shift as usize] = letter;
+                self.next_observation = Observation(self.next_observation.0.max(letter.0 + 1));
+                letter
+            }
+        };
+        self.sides[in_direction as usize][in_shift as usize] = observation;
+        self.history.push(Probe {
+            laser,
+            result,
+            observation,
+        });
+        Some(observation)
+    }
+
+    /// The letter for the next laser coming out somewhere else. Once the alphabet is used up, e.g.
+    /// after reading observations with a `Z`, letters no longer on the border are reused.
+    fn free_letter(&self) -> Option<Observation> {
+        if usize::from(self.next_observation.0 - 3) < LETTER_COUNT {
+            return Some(self.next_observation);
+        }
+        (0..LETTER_COUNT as u8)
+            .map(|i| Observation(3 + i))
+            .find(|letter| !self.sides.iter().flatten().any(|obs| obs == letter))
+    }
+
+    /// The probes made so far, in order. Observations read from text have no history.
+    pub fn history(&self) -> &[Probe] {
+        &self.history
+    }
+
+    /// Takes back the last probe, as if the laser had never been fired. The letters of the
+    /// remaining lasers are renamed, so they stay consecutive in the order they were fired.
+    pub fn undo(&mut self) -> Option<Probe> {
+        let probe = self.history.pop()?;
+        let (shift, direction) = probe
+            .laser
+            .deconstruct(self.size)
             .expect("Probing should only happen with side-lasers.");
-        let (laser_out, move_count) = laser.traverse_grid(grid);
+        self.forget(direction, shift);
+        self.compact_letters();
+        Some(probe)
+    }
 
-        if move_count <= 1 && laser_out.is_some() {
-            // Reflection
-            self.sides[in_direction as usize][in_shift as usize] = LASER_REFLECTED;
-        } else if let Some(laser_out) = laser_out {
-            // Laser came out somewhere else
-            let (out_shift, out_direction) = laser_out
-                .deconstruct()
-                .expect("Traversal should return the laser on the border.");
-            if in_direction == out_direction && in_shift == out_shift {
-                self.sides[in_direction as usize][in_shift as usize] = LASER_REFLECTED;
-            } else {
-                self.sides[in_direction as usize][in_shift as usize] = self.next_observation;
-                self.sides[out_direction as usize][out_shift as usize] = self.next_observation;
-                self.next_observation = Observation(self.next_observation.0 + 1);
+    /// Renames the letters to A, B, C, ... keeping their order.
+    fn compact_letters(&mut self) {
+        let mut letters: Vec<Observation> = self
+            .sides
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|obs| obs.is_letter())
+            .collect();
+        letters.sort_by_key(|obs| obs.0);
+        letters.dedup();
+        let rename = |obs: Observation| match letters.iter().position(|&letter| letter == obs) {
+            Some(i) => Observation(3 + i as u8),
+            None => obs,
+        };
+        for entry in self.sides.iter_mut().flatten() {
+            *entry = rename(*entry);
+        }
+        for probe in &mut self.history {
+            probe.observation = rename(probe.observation);
+        }
+        self.next_observation = Observation(3 + letters.len() as u8);
+    }
+
+    /// Removes what was observed at the given border position, as if the laser had never been
+    /// fired. For a laser that came out somewhere else both ends are removed, and so is the probe
+    /// from the history.
+    pub fn forget(&mut self, direction: Direction, shift: u8) {
+        let obs = self.sides[direction as usize][shift as usize];
+        for side in &mut self.sides {
+            for entry in side.iter_mut() {
+                if *entry == obs && obs.is_letter() {
+                    *entry = NOT_PROBED;
+                }
             }
-        } else {
-            // Laser absorbed
-            self.sides[in_direction as usize][in_shift as usize] = LASER_ABSORBED;
         }
+        self.sides[direction as usize][shift as usize] = NOT_PROBED;
+
+        let (size, sides) = (self.size, &self.sides);
+        self.history.retain(|probe| {
+            let (shift, direction) = probe.laser.deconstruct(size).unwrap();
+            sides[direction as usize][shift as usize] != NOT_PROBED
+        });
+    }
+
+    /// Shoots the laser through the grid following the rules of these observations.
+    pub fn traverse(&self, laser: LaserTip, grid: &AtomGrid) -> TraversalResult {
+        assert_eq!(
+            self.size,
+            grid.size(),
+            "Observations are for a different grid"
+        );
+        laser.traverse_grid(grid, &self.rules)
+    }
+
+    /// Checks whether the given grid would produce all the observations made so far. Positions
+    /// that were not probed carry no information and are ignored. Letters only need to pair up
+    /// the same entry and exit, their exact value does not matter.
+    pub fn is_consistent_with(&self, grid: &AtomGrid) -> bool {
+        self.iter().into_iter().all(|(direction, shift, obs)| {
+            if obs == NOT_PROBED {
+                return true;
+            }
+            let laser = LaserTip::new(shift, direction, self.size);
+            match self.traverse(laser, grid) {
+                TraversalResult::Absorbed { .. } => obs == LASER_ABSORBED,
+                TraversalResult::Looped => obs == LASER_LOOPED,
+                TraversalResult::Reflected => obs == LASER_REFLECTED,
+                TraversalResult::Exited {
+                    side: out_direction,
+                    shift: out_shift,
+                } => {
+                    obs.is_letter() && self.sides[out_direction as usize][out_shift as usize] == obs
+                }
+            }
+        })
+    }
+
+    /// What was observed at the border position where a laser enters moving in the given
+    /// direction.
+    pub fn get(&self, direction: Direction, shift: u8) -> Observation {
+        self.sides[direction as usize][shift as usize]
     }
 
     /// Iterates over all observations
     pub fn iter(&self) -> Vec<(Direction, u8, Observation)> {
         let mut result = vec![];
         for direction in Direction::all() {
-            for shift in 0..GRID_SIZE {
-                let obs = self.sides[direction as usize][shift];
+            for (shift, &obs) in self.sides[direction as usize].iter().enumerate() {
                 result.push((direction, shift as u8, obs));
             }
         }
@@ -84,12 +277,166 @@ pub struct Observation(u8);
 pub const NOT_PROBED: Observation = Observation(0); // Special value
 pub const LASER_ABSORBED: Observation = Observation(1); // Special value
 pub const LASER_REFLECTED: Observation = Observation(2); // Special value
+pub const LASER_LOOPED: Observation = Observation(u8::MAX); // Special value, after all letters
 
-const ALPHABET: &'static str = "ABCDEFGHKLMNPRSTUVWYZ"; // Exclude some letters
+const ALPHABET: &str = "ABCDEFGHKLMNPRSTUVWYZ"; // Exclude some letters
+
+/// The number of different letters, see [GridSize::try_new].
+pub(crate) const LETTER_COUNT: usize = ALPHABET.len();
 
 impl Observation {
-    pub(crate) fn is_letter(self) -> bool {
-        self.0 >= 3
+    /// Whether the laser came out somewhere else. Both ends show the same letter.
+    pub fn is_letter(self) -> bool {
+        self.0 >= 3 && self != LASER_LOOPED
+    }
+
+    /// Reads an observation as it is displayed. Like in the text format of [Observations], `x`,
+    /// `r` and `loop` may be used instead of `×`, `⇄` and `∞`.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "?" => Some(NOT_PROBED),
+            "×" | "x" => Some(LASER_ABSORBED),
+            "⇄" | "r" => Some(LASER_REFLECTED),
+            "∞" | "loop" => Some(LASER_LOOPED),
+            _ => ALPHABET
+                .chars()
+                .position(|c| c.to_string() == symbol)
+                .map(|i| Observation(3 + i as u8)),
+        }
+    }
+}
+
+/// Observations are stored as they are displayed.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Observation {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Observation {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Observation::from_symbol(&symbol)
+            .ok_or_else(|| serde::de::Error::custom(format!("Unknown observation \"{}\"", symbol)))
+    }
+}
+
+/// The stored form of [Observations]. The sides are indexed by laser direction like in
+/// [Observations] itself.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedObservations {
+    size: GridSize,
+    rules: Rules,
+    sides: [Vec<Observation>; 4],
+    #[serde(default)]
+    history: Vec<Probe>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Observations> for SerializedObservations {
+    fn from(observations: Observations) -> Self {
+        SerializedObservations {
+            size: observations.size,
+            rules: observations.rules,
+            sides: observations.sides,
+            history: observations.history,
+        }
+    }
+}
+
+/// Checks that the sides fit the size, that every letter appears exactly twice and that the
+/// history is either empty or explains the sides, with every laser fired once.
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedObservations> for Observations {
+    type Error = String;
+
+    fn try_from(stored: SerializedObservations) -> Result<Self, String> {
+        let mut this = Observations::with_rules(stored.size, stored.rules);
+        for direction in Direction::all() {
+            let side = &stored.sides[direction as usize];
+            if side.len() != this.sides[direction as usize].len() {
+                return Err(format!(
+                    "{} observations for lasers moving {:?} instead of {}",
+                    side.len(),
+                    direction,
+                    this.sides[direction as usize].len()
+                ));
+            }
+        }
+        this.sides = stored.sides;
+
+        let mut next_observation = this.next_observation;
+        for obs in this.sides.iter().flatten().filter(|obs| obs.is_letter()) {
+            let count = this
+                .sides
+                .iter()
+                .flatten()
+                .filter(|&other| other == obs)
+                .count();
+            if count != 2 {
+                return Err(format!("{} appears {} times instead of twice", obs, count));
+            }
+            next_observation = Observation(next_observation.0.max(obs.0 + 1));
+        }
+        this.next_observation = next_observation;
+
+        // Each laser shows its observation at its entry and, if it came out elsewhere, at its
+        // exit. A complete history explains every observation exactly once.
+        let mut explained = this.sides.clone().map(|side| vec![false; side.len()]);
+        for probe in &stored.history {
+            let entry = probe
+                .laser
+                .deconstruct(this.size)
+                .filter(|&(shift, direction)| {
+                    LaserTip::new(shift, direction, this.size) == probe.laser
+                        && shift < this.size.side_length(direction)
+                });
+            let mut ends = entry.into_iter().collect::<Vec<_>>();
+            if let TraversalResult::Exited { side, shift } = probe.result {
+                ends.push((shift, side));
+            }
+            for &(shift, direction) in &ends {
+                let Some(seen) = explained[direction as usize].get_mut(shift as usize) else {
+                    return Err(format!(
+                        "The probe {:?} exits outside the grid",
+                        probe.laser
+                    ));
+                };
+                if *seen {
+                    return Err(format!("The probe {:?} was made twice", probe.laser));
+                }
+                *seen = true;
+            }
+            let kind_matches = match probe.result {
+                TraversalResult::Absorbed { .. } => probe.observation == LASER_ABSORBED,
+                TraversalResult::Looped => probe.observation == LASER_LOOPED,
+                TraversalResult::Reflected => probe.observation == LASER_REFLECTED,
+                TraversalResult::Exited { .. } => probe.observation.is_letter(),
+            };
+            let matches = kind_matches
+                && ends.iter().all(|&(shift, direction)| {
+                    this.sides[direction as usize][shift as usize] == probe.observation
+                });
+            if entry.is_none() || !matches || probe.observation == NOT_PROBED {
+                return Err(format!(
+                    "The probe {:?} does not match the sides",
+                    probe.laser
+                ));
+            }
+        }
+        let unexplained = this.sides.iter().zip(&explained).any(|(side, seen)| {
+            side.iter()
+                .zip(seen)
+                .any(|(&obs, &seen)| obs != NOT_PROBED && !seen)
+        });
+        if !stored.history.is_empty() && unexplained {
+            return Err("The history does not explain all observations".to_string());
+        }
+        this.history = stored.history;
+        Ok(this)
     }
 }
 
@@ -99,42 +446,265 @@ impl Display for Observation {
             NOT_PROBED => f.write_str("?"),
             LASER_ABSORBED => f.write_str("×"),
             LASER_REFLECTED => f.write_str("⇄"),
+            LASER_LOOPED => f.write_str("∞"),
             Observation(3) => f.write_str("A"),
             Observation(i) => f.write_char(ALPHABET.chars().nth((i - 3) as usize).unwrap()),
         }
     }
 }
 
+/// Writes the observations in the text format of [draw] with an empty grid.
+impl Display for Observations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&draw(&AtomGrid::new(self.size), self)?)
+    }
+}
+
+/// A line of the text format could not be understood.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Reads observations in the layout [draw] prints, so puzzles from other sources can be typed in:
+///
+/// ```text
+///    ? × A ? ⇄
+///  A . . . . . ?
+///  ? . . . . . B
+///  × . . . . . ?
+///    ? ? ? B ?
+/// ```
+///
+/// Tokens are separated by whitespace. The fields inside the grid are ignored, but there has to be
+/// one token for each of them. `x`, `r` and `loop` may be typed instead of `×`, `⇄` and `∞`.
+/// Lasers coming out somewhere else may be labelled with any word, e.g. numbers, as long as each
+/// label appears exactly twice. Empty lines and lines starting with `#` are skipped.
+impl FromStr for Observations {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        let lines: Vec<(usize, Vec<&str>)> = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+            .filter(|(_, tokens)| !tokens.is_empty() && !tokens[0].starts_with('#'))
+            .collect();
+        let error = |line: usize, message: String| ParseError { line, message };
+
+        let [(top_line, top), rows @ .., (bottom_line, bottom)] = &lines[..] else {
+            return Err(error(
+                lines.last().map_or(1, |(line, _)| *line),
+                "Expected a top border, at least one row and a bottom border".to_string(),
+            ));
+        };
+        let width = top.len();
+        let height = rows.len();
+        let size = GridSize::try_new(width, height).map_err(|message| error(*top_line, message))?;
+        if bottom.len() != width {
+            return Err(error(
+                *bottom_line,
+                format!("Expected {} observations like on top", width),
+            ));
+        }
+
+        // Collect all border tokens with the laser entering there.
+        let mut tokens = vec![];
+        for (shift, token) in top.iter().enumerate() {
+            tokens.push((*top_line, Down, shift, *token));
+        }
+        for (y, (line, row)) in rows.iter().enumerate() {
+            if row.len() != width + 2 {
+                return Err(error(
+                    *line,
+                    format!(
+                        "Expected {} fields between two observations, found {} tokens",
+                        width,
+                        row.len()
+                    ),
+                ));
+            }
+            tokens.push((*line, Right, y, row[0]));
+            tokens.push((*line, Left, y, row[width + 1]));
+        }
+        for (shift, token) in bottom.iter().enumerate() {
+            tokens.push((*bottom_line, Up, shift, *token));
+        }
+
+        // Letters of our own alphabet keep their meaning, other labels get the unused letters.
+        let mut labels: Vec<(&str, Observation, usize)> = vec![];
+        for &(_, _, _, token) in &tokens {
+            if let Some(i) = ALPHABET.chars().position(|c| c.to_string() == token) {
+                if !labels.iter().any(|(label, _, _)| *label == token) {
+                    labels.push((token, Observation(3 + i as u8), 0));
+                }
+            }
+        }
+        let mut unused = (0..ALPHABET.len() as u8)
+            .map(|i| Observation(3 + i))
+            .filter(|obs| !labels.iter().any(|(_, used, _)| used == obs))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let mut this = Observations::new(size);
+        for &(line, direction, shift, token) in &tokens {
+            let obs = match token {
+                "?" => NOT_PROBED,
+                "×" | "x" => LASER_ABSORBED,
+                "⇄" | "r" => LASER_REFLECTED,
+                "∞" | "loop" => LASER_LOOPED,
+                label => {
+                    let index = match labels.iter().position(|(l, _, _)| *l == label) {
+                        Some(index) => index,
+                        None => {
+                            let obs = unused.next().ok_or_else(|| {
+                                error(line, "Too many different labels".to_string())
+                            })?;
+                            labels.push((label, obs, 0));
+                            labels.len() - 1
+                        }
+                    };
+                    labels[index].2 += 1;
+                    labels[index].1
+                }
+            };
+            this.sides[direction as usize][shift] = obs;
+        }
+
+        for (label, obs, count) in &labels {
+            if *count != 2 {
+                let line = tokens.iter().find(|t| t.3 == *label).map_or(1, |t| t.0);
+                return Err(error(
+                    line,
+                    format!(
+                        "Label {} appears {} times, but every laser has an entry and an exit",
+                        label, count
+                    ),
+                ));
+            }
+            this.next_observation = Observation(this.next_observation.0.max(obs.0 + 1));
+        }
+
+        Ok(this)
+    }
+}
+
 pub fn draw(grid: &AtomGrid, observations: &Observations) -> Result<String, std::fmt::Error> {
+    draw_cells(observations, |v| grid.cell(v).symbol())
+}
+
+/// Like [draw], but also shows the paths of the given lasers through the grid. Straight moves are
+/// shown as arrows, deflections as corners and reflections as double arrows. Fields crossed in
+/// different ways are shown as `┼`.
+pub fn draw_paths(
+    grid: &AtomGrid,
+    observations: &Observations,
+    lasers: &[LaserTip],
+) -> Result<String, std::fmt::Error> {
+    let size = grid.size();
+    let mut path = vec![None; size.cell_count()];
+    for laser in lasers {
+        let mut previous = None;
+        for step in laser.trace(grid, &observations.rules) {
+            let v = step.from.position();
+            // A laser turning in front of an object stays on its field, which shows the turn.
+            if !v.in_grid(size) || previous.replace(v) == Some(v) {
+                continue;
+            }
+            let direction = step.from.direction();
+            let c = match (step.rule, step.to) {
+                (MoveRule::Reflected, _) if matches!(direction, Left | Right) => '⇄',
+                (MoveRule::Reflected, _) => '⇅',
+                // Mirrors turn the laser on their own field, which keeps showing the mirror.
+                (MoveRule::DeflectedLeft | MoveRule::DeflectedRight, Some(to))
+                    if to.position() != step.from.forward().position() =>
+                {
+                    corner(direction.flip(), to.direction())
+                }
+                _ => arrow(direction),
+            };
+            let cell = &mut path[size.index(v)];
+            *cell = Some(match *cell {
+                None => c,
+                Some(previous) if previous == c => c,
+                Some(previous) => match (previous, c) {
+                    ('→', '←') | ('←', '→') => '↔',
+                    ('↑', '↓') | ('↓', '↑') => '↕',
+                    _ => '┼',
+                },
+            });
+        }
+    }
+
+    draw_cells(observations, |v| {
+        match (grid.cell(v), path[size.index(v)]) {
+            (Cell::Empty, Some(c)) => c,
+            (cell, _) => cell.symbol(),
+        }
+    })
+}
+
+fn arrow(direction: Direction) -> char {
+    match direction {
+        Up => '↑',
+        Down => '↓',
+        Left => '←',
+        Right => '→',
+    }
+}
+
+/// The box drawing character connecting the two sides of a field.
+fn corner(a: Direction, b: Direction) -> char {
+    match (a, b) {
+        (Left, Up) | (Up, Left) => '┘',
+        (Right, Up) | (Up, Right) => '└',
+        (Left, Down) | (Down, Left) => '┐',
+        (Right, Down) | (Down, Right) => '┌',
+        _ => unreachable!("Lasers only turn by 90 degrees"),
+    }
+}
+
+/// Draws the observations around the grid, with `cell` giving the character of each field.
+fn draw_cells(
+    observations: &Observations,
+    cell: impl Fn(I8Vec2) -> char,
+) -> Result<String, std::fmt::Error> {
+    let size = observations.size();
     let mut f = String::new();
     // first, display the row above with lasers pointing down
     f.write_str("  ")?;
-    for obs in observations.sides[Down as usize] {
+    for obs in &observations.sides[Down as usize] {
         f.write_str(&format!(" {}", obs))?;
     }
     f.write_char('\n')?;
 
-    let left_border = observations.sides[Right as usize];
-    let right_border = observations.sides[Left as usize];
+    let left_border = &observations.sides[Right as usize];
+    let right_border = &observations.sides[Left as usize];
 
     // Show rows
-    for y in 0..GRID_SIZE {
+    for y in 0..size.height() as usize {
         let left_obs = left_border[y];
         let right_obs = right_border[y];
 
         f.write_str(&format!(" {}", left_obs))?;
-        for x in 0..GRID_SIZE {
-            if grid.get(I8Vec2::new(x as i8, y as i8)) {
-                f.write_str(" o")?;
-            } else {
-                f.write_str(" .")?;
-            }
+        for x in 0..size.width() as usize {
+            write!(f, " {}", cell(I8Vec2::new(x as i8, y as i8)))?;
         }
         f.write_str(&format!(" {}\n", right_obs))?;
     }
 
     f.write_str("  ")?;
-    for obs in observations.sides[Up as usize] {
+    for obs in &observations.sides[Up as usize] {
         f.write_str(&format!(" {}", obs))?;
     }
     f.write_char('\n')?;
@@ -144,24 +714,30 @@ pub fn draw(grid: &AtomGrid, observations: &Observations) -> Result<String, std:
 
 #[cfg(test)]
 mod tests {
-    use crate::atom_grid::AtomGrid;
+    use crate::atom_grid::{AtomGrid, GridSize, DEFAULT_SIZE};
+    use crate::i8vec2::I8Vec2;
     use crate::laser::Direction::*;
     use crate::laser::LaserTip;
-    use crate::observation::{Observations, LASER_ABSORBED, LASER_REFLECTED};
+    use crate::observation::{
+        draw, draw_paths, Observation, Observations, LASER_ABSORBED, LASER_LOOPED, LASER_REFLECTED,
+        NOT_PROBED,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn observation_after_probing() {
-        let grid = AtomGrid::from_bitboard(54043333103714304);
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 54043333103714304);
         println!("{}", grid);
 
         let mut observations = Observations::default();
 
         // Probe along the left side, shooting lasers to the right.
         for i in 0..8 {
-            observations.probe(LaserTip::new(i, Right), &grid);
+            observations.probe(LaserTip::new(i, Right, DEFAULT_SIZE), &grid);
         }
 
-        let obs = observations.sides[Right as usize];
+        let obs = &observations.sides[Right as usize];
         println!("{:?}", obs);
         assert_eq!(obs[0], LASER_REFLECTED);
         assert_eq!(obs[1], LASER_ABSORBED);
@@ -172,4 +748,298 @@ mod tests {
         assert_eq!(obs[6].0, 3);
         assert_eq!(obs[7].0, 4);
     }
+
+    #[test]
+    fn observe_rectangular_grid() {
+        let size = GridSize::new(5, 3);
+        let mut grid = AtomGrid::new(size);
+        grid.set(I8Vec2::new(2, 1), true);
+
+        let observations = Observations::observe_all(&grid);
+        assert_eq!(observations.sides[Down as usize].len(), 5);
+        assert_eq!(observations.sides[Right as usize].len(), 3);
+        assert_eq!(observations.iter().len(), 16);
+        assert!(observations
+            .iter()
+            .iter()
+            .all(|(_, _, obs)| *obs != NOT_PROBED));
+
+        // Straight through the middle row and column the laser hits the atom.
+        assert_eq!(observations.sides[Right as usize][1], LASER_ABSORBED);
+        assert_eq!(observations.sides[Up as usize][2], LASER_ABSORBED);
+        println!("{}", draw(&grid, &observations).unwrap());
+    }
+
+    #[test]
+    fn forget_observations() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 54043333103714304);
+        let mut observations = Observations::observe_all(&grid);
+        let letter = observations.sides[Right as usize][6];
+        assert!(letter.is_letter());
+        observations.forget(Right, 6);
+        assert!(observations.iter().iter().all(|(_, _, obs)| *obs != letter));
+
+        observations.forget(Right, 0);
+        assert_eq!(observations.sides[Right as usize][0], NOT_PROBED);
+        assert_eq!(observations.sides[Right as usize][1], LASER_ABSORBED);
+        assert!(observations.is_consistent_with(&grid));
+    }
+
+    #[test]
+    fn probe_history_and_undo() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 54043333103714304);
+        let mut observations = Observations::new(DEFAULT_SIZE);
+        let mut states = vec![observations.clone()];
+        for shift in 0..8 {
+            if observations.get(Right, shift) == NOT_PROBED {
+                observations.probe(LaserTip::new(shift, Right, DEFAULT_SIZE), &grid);
+                states.push(observations.clone());
+            }
+        }
+        let history = observations.history();
+        assert_eq!(history.len(), states.len() - 1);
+        assert_eq!(history[0].laser, LaserTip::new(0, Right, DEFAULT_SIZE));
+        for probe in history {
+            let (shift, direction) = probe.laser.deconstruct(DEFAULT_SIZE).unwrap();
+            assert_eq!(probe.observation, observations.get(direction, shift));
+            assert_eq!(probe.result, observations.traverse(probe.laser, &grid));
+        }
+
+        // Undoing goes back through the same states.
+        while observations.undo().is_some() {
+            states.pop();
+            assert_eq!(&observations, states.last().unwrap());
+            assert_eq!(observations.history().len(), states.len() - 1);
+        }
+        assert_eq!(states.len(), 1);
+
+        // After forgetting a letter in the middle, the letters are renamed to stay consecutive.
+        let mut observations = Observations::observe_all(&grid);
+        let count = observations.history().len();
+        let first_letter = observations
+            .history()
+            .iter()
+            .find(|probe| probe.observation.is_letter())
+            .unwrap();
+        let (shift, direction) = first_letter.laser.deconstruct(DEFAULT_SIZE).unwrap();
+        observations.forget(direction, shift);
+        assert_eq!(observations.history().len(), count - 1);
+        observations.undo().unwrap();
+        assert_eq!(observations.history().len(), count - 2);
+
+        let letters: Vec<Observation> = observations
+            .iter()
+            .into_iter()
+            .map(|(_, _, obs)| obs)
+            .filter(|obs| obs.is_letter())
+            .collect();
+        for i in 0..letters.len() / 2 {
+            let letter = Observation(3 + i as u8);
+            assert_eq!(letters.iter().filter(|&&obs| obs == letter).count(), 2);
+        }
+        for probe in observations.history() {
+            let (shift, direction) = probe.laser.deconstruct(DEFAULT_SIZE).unwrap();
+            assert_eq!(probe.observation, observations.get(direction, shift));
+        }
+    }
+
+    #[test]
+    fn probe_only_unknown_positions() {
+        let grid = AtomGrid::new(GridSize::new(5, 3));
+        let text = "
+               ?  ?  ?  ?  ?
+             Z .  .  .  .  .  ?
+             ?  . .  .  .  .  ?
+             ?  . .  .  .  .  ?
+               ?  ?  ?  ?  Z
+        ";
+        let mut observations: Observations = text.parse().unwrap();
+
+        // One laser enters at a Z, the other would come out at the other Z.
+        let before = observations.clone();
+        assert_eq!(
+            observations.probe(LaserTip::new(0, Right, grid.size()), &grid),
+            None
+        );
+        assert_eq!(
+            observations.probe(LaserTip::new(4, Down, grid.size()), &grid),
+            None
+        );
+        assert_eq!(observations, before);
+        assert!(observations.history().is_empty());
+
+        // The alphabet ends with Z, so a free letter is used again.
+        let obs = observations.probe(LaserTip::new(1, Right, grid.size()), &grid);
+        assert_eq!(obs, Some(Observation(3)));
+        assert_eq!(observations.get(Left, 1), Observation(3));
+        assert!(observations.to_string().contains('A'));
+        assert_eq!(
+            observations.probe(LaserTip::new(1, Left, grid.size()), &grid),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_drawn_observations() {
+        let mut rng = StdRng::seed_from_u64(10);
+        for size in [DEFAULT_SIZE, GridSize::new(5, 5), GridSize::new(10, 6)] {
+            for atom_count in 1..=6 {
+                let grid = AtomGrid::random(size, atom_count, &mut rng);
+                let observations = Observations::observe_all(&grid);
+                let text = draw(&grid, &observations).unwrap();
+                assert_eq!(text.parse(), Ok(observations.clone()));
+                assert_eq!(observations.to_string().parse(), Ok(observations));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_typed_observations() {
+        let text = "
+            # Lasers may be labelled with numbers as well.
+               ?  x  1  ?  r
+             1 .  .  .  .  .  ?
+             ?  . .  .  .  .  2
+             ×  . .  .  .  .  ?
+               ?  ?  ?  2  ?
+        ";
+        let observations: Observations = text.parse().unwrap();
+        assert_eq!(observations.size(), GridSize::new(5, 3));
+        assert_eq!(observations.sides[Down as usize][1], LASER_ABSORBED);
+        assert_eq!(observations.sides[Down as usize][4], LASER_REFLECTED);
+        assert_eq!(observations.sides[Right as usize][2], LASER_ABSORBED);
+        assert_eq!(observations.sides[Up as usize][0], NOT_PROBED);
+        let first = observations.sides[Down as usize][2];
+        let second = observations.sides[Left as usize][1];
+        assert!(first.is_letter() && second.is_letter() && first != second);
+        assert_eq!(observations.sides[Right as usize][0], first);
+        assert_eq!(observations.sides[Up as usize][3], second);
+
+        let error = |text: &str| text.parse::<Observations>().unwrap_err();
+        assert_eq!(error("").line, 1);
+        assert_eq!(error("  ? ?\n? . . ?\n  ? ? ?").line, 3);
+        assert_eq!(error("  ? ?\n? . ?\n  ? ?").line, 2);
+        assert_eq!(error("  A ?\n? . . ?\n  ? ?").line, 1);
+        assert_eq!(error("  A A\n? . . A\n  ? ?").line, 1);
+        assert_eq!(error("  ? ?\n  ? ?\n").line, 1);
+    }
+
+    #[test]
+    fn trapped_laser() {
+        let grid: AtomGrid = " o . o\n . . .\n . . .\n o . o\n".parse().unwrap();
+        let rules = "no-edge-reflection".parse().unwrap();
+        let mut observations = Observations::with_rules(grid.size(), rules);
+        let obs = observations
+            .probe(LaserTip::new(1, Up, grid.size()), &grid)
+            .unwrap();
+        assert_eq!(obs, LASER_LOOPED);
+        assert!(!obs.is_letter());
+        assert!(observations.is_consistent_with(&grid));
+        assert!(!observations.is_consistent_with(&AtomGrid::new(grid.size())));
+
+        let text = observations.to_string();
+        assert_eq!(text.lines().last(), Some("   ? ∞ ?"));
+        let mut parsed: Observations = text.replace('∞', "loop").parse().unwrap();
+        parsed.set_rules(rules);
+        assert_eq!(parsed, observations);
+    }
+
+    #[test]
+    fn draw_laser_paths() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let observations = Observations::observe_all(&grid);
+        let lasers = [
+            LaserTip::new(1, Right, DEFAULT_SIZE),
+            LaserTip::new(3, Right, DEFAULT_SIZE),
+        ];
+        let text = draw_paths(&grid, &observations, &lasers).unwrap();
+        println!("{}", text);
+        let rows: Vec<&str> = text.lines().collect();
+        // Deflected up in the second row, turned around in the fourth row.
+        assert_eq!(rows[1], " F . ↑ . . . . . . F");
+        assert_eq!(rows[2], " C → ┘ . . . . . . D");
+        assert_eq!(rows[4], " G → ┐ . . . . . . E");
+        assert_eq!(rows[5], " × . ↓ . o . . . . ⇄");
+        assert_eq!(rows[6], " G ← ┘ . . . . . o ×");
+
+        // A reflection right at the border does not enter the grid.
+        assert_eq!(observations.sides[Up as usize][5], LASER_REFLECTED);
+        let lasers = [LaserTip::new(5, Up, DEFAULT_SIZE)];
+        assert_eq!(
+            draw_paths(&grid, &observations, &lasers),
+            draw(&grid, &observations)
+        );
+
+        let size = GridSize::new(4, 3);
+        let mut grid = AtomGrid::new(size);
+        grid.set(I8Vec2::new(2, 0), true);
+        grid.set(I8Vec2::new(2, 2), true);
+        let observations = Observations::observe_all(&grid);
+        let lasers = [LaserTip::new(1, Right, size)];
+        let text = draw_paths(&grid, &observations, &lasers).unwrap();
+        assert_eq!(text.lines().nth(2), Some(" ⇄ ↔ ⇄ . . ⇄"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let observations: Observations = "  ×\n × . ×\n  ×".parse().unwrap();
+        let json = serde_json::to_string(&observations).unwrap();
+        assert_eq!(
+            json,
+            r#"{"size":{"width":1,"height":1},"rules":{"edge_reflection":true,"absorption":true},"sides":[["×"],["×"],["×"],["×"]],"history":[]}"#
+        );
+
+        // The history has to match the sides.
+        let mut grid = AtomGrid::new(GridSize::square(1));
+        grid.set(I8Vec2::new(0, 0), true);
+        let probed = serde_json::to_string(&Observations::observe_all(&grid)).unwrap();
+        assert!(serde_json::from_str::<Observations>(&probed).is_ok());
+        let wrong_history = probed.replacen(r#""observation":"×""#, r#""observation":"⇄""#, 1);
+        assert!(serde_json::from_str::<Observations>(&wrong_history).is_err());
+        let off_grid = probed.replacen(
+            r#"{"x":1,"y":0},"direction":"Left""#,
+            r#"{"x":3,"y":0},"direction":"Left""#,
+            1,
+        );
+        assert_ne!(off_grid, probed);
+        assert!(serde_json::from_str::<Observations>(&off_grid).is_err());
+
+        // The history has to explain every observation, firing each laser once.
+        let read = |value: &serde_json::Value| {
+            serde_json::from_value::<Observations>(value.clone()).map(|_| ())
+        };
+        let mut value: serde_json::Value = serde_json::from_str(&probed).unwrap();
+        let history = value["history"].as_array().unwrap().clone();
+        value["history"] = serde_json::json!([history[0], history[0]]);
+        assert!(read(&value).is_err());
+        value["history"] = serde_json::json!(history[1..]);
+        assert!(read(&value).is_err());
+        value["history"] = serde_json::json!([]);
+        assert!(read(&value).is_ok());
+
+        // The result has to be what the sides show.
+        let mut reflected = history.clone();
+        reflected[0]["result"] = serde_json::json!("Reflected");
+        value["history"] = serde_json::json!(reflected);
+        assert!(read(&value).is_err());
+        value["history"] = serde_json::json!(history);
+        assert!(read(&value).is_ok());
+
+        // Each letter has to appear on both ends of the laser.
+        let unpaired = json.replacen("×", "A", 1);
+        assert!(serde_json::from_str::<Observations>(&unpaired).is_err());
+        let missing = json.replacen(r#"["×"],"#, "[],", 1);
+        assert!(serde_json::from_str::<Observations>(&missing).is_err());
+
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let grid = AtomGrid::random(DEFAULT_SIZE, 5, &mut rng);
+            let observations = Observations::observe_all(&grid);
+            let json = serde_json::to_string(&observations).unwrap();
+            let read: Observations = serde_json::from_str(&json).unwrap();
+            assert_eq!(read, observations);
+            assert_eq!(read.history(), observations.history());
+        }
+    }
 }
@@ -0,0 +1,106 @@
+//! Scoring like in the classic Black Box game. Lower scores are better.
+//!
+//! Every marker on the border costs a point: absorptions and reflections have one marker, a laser
+//! coming out somewhere else has two (entry and exit, both showing the same letter). Every atom
+//! that was not found costs a penalty on top.
+
+use crate::game::GuessResult;
+use crate::observation::{Observation, Observations, NOT_PROBED};
+
+/// Points for each absorption or reflection.
+pub const POINTS_PER_MARKER: u32 = 1;
+/// Points for a laser that came out somewhere else.
+pub const POINTS_PER_LETTER_PAIR: u32 = 2 * POINTS_PER_MARKER;
+/// Points for each atom that was placed wrongly.
+pub const POINTS_PER_WRONG_ATOM: u32 = 5;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    /// Points for firing lasers.
+    pub probe_points: u32,
+    /// Points for atoms that were not found.
+    pub penalty_points: u32,
+}
+
+impl Score {
+    /// Scores a finished game.
+    pub fn new(observations: &Observations, guess: GuessResult) -> Self {
+        Score {
+            probe_points: probe_points(observations),
+            penalty_points: wrong_atoms(guess) * POINTS_PER_WRONG_ATOM,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.probe_points + self.penalty_points
+    }
+}
+
+/// Points for the given observation at the entry of a laser. Letters are charged for both ends.
+pub fn probe_cost(observation: Observation) -> u32 {
+    if observation == NOT_PROBED {
+        0
+    } else if observation.is_letter() {
+        POINTS_PER_LETTER_PAIR
+    } else {
+        POINTS_PER_MARKER
+    }
+}
+
+/// Points for all lasers fired so far, one per marker on the border.
+pub fn probe_points(observations: &Observations) -> u32 {
+    observations
+        .iter()
+        .into_iter()
+        .filter(|(_, _, obs)| *obs != NOT_PROBED)
+        .count() as u32
+        * POINTS_PER_MARKER
+}
+
+/// A misplaced atom is both a wrong mark and a missed atom, it is only counted once. Atoms that
+/// were not marked at all count as well.
+pub fn wrong_atoms(guess: GuessResult) -> u32 {
+    guess.wrong.max(guess.missed) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::{AtomGrid, DEFAULT_SIZE};
+    use crate::laser::Direction::*;
+    use crate::laser::LaserTip;
+    use crate::observation::{LASER_ABSORBED, LASER_REFLECTED};
+
+    #[test]
+    fn test_probe_points() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 54043333103714304);
+        let mut observations = Observations::new(DEFAULT_SIZE);
+        let mut points = 0;
+        for i in 0..8 {
+            let obs = observations
+                .probe(LaserTip::new(i, Right, DEFAULT_SIZE), &grid)
+                .unwrap();
+            points += probe_cost(obs);
+            assert_eq!(probe_points(&observations), points);
+        }
+        // Three reflections, three absorptions and two letter pairs.
+        assert_eq!(probe_cost(LASER_REFLECTED), 1);
+        assert_eq!(probe_cost(LASER_ABSORBED), 1);
+        assert_eq!(points, 3 + 3 + 2 * 2);
+    }
+
+    #[test]
+    fn test_score() {
+        let observations = Observations::new(DEFAULT_SIZE);
+        let guess = |correct, wrong, missed| GuessResult {
+            correct,
+            wrong,
+            missed,
+        };
+        assert_eq!(Score::new(&observations, guess(5, 0, 0)).total(), 0);
+        // One atom in the wrong place.
+        assert_eq!(Score::new(&observations, guess(4, 1, 1)).total(), 5);
+        // Two atoms not marked at all.
+        assert_eq!(Score::new(&observations, guess(3, 0, 2)).total(), 10);
+    }
+}
@@ -0,0 +1,352 @@
+//! Games that are saved to a file, so the player can quit and continue later, and finished games
+//! can be replayed laser by laser.
+//!
+//! Sessions are saved as text: the rules, the time played in seconds, the player's moves in the
+//! command syntax of the [game] and finally the hidden grid in the format it is displayed in.
+//! Lasers that were taken back come first, each followed by `undo`, so they are still charged.
+//!
+//! ```text
+//! # laser-puzzle game
+//! rules classic
+//! time 95
+//! fire bottom 3
+//! undo
+//! fire left 2
+//! fire top 5
+//! mark 3 4
+//! hidden
+//!  . . . o .
+//!  ...
+//! ```
+//!
+//! The score is not saved, it follows from the lasers and the marks, see [Game::score].
+
+use crate::atom_grid::AtomGrid;
+use crate::game;
+use crate::game::{Command, Game, GuessResult};
+use crate::laser::{Direction, Rules};
+use crate::observation::{Observation, Observations};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+/// A game together with the time spent on it.
+pub struct GameSession {
+    game: Game,
+    /// Time played before the current sitting.
+    earlier: Duration,
+    /// When the current sitting started. The clock does not run for finished games.
+    resumed: Option<Instant>,
+}
+
+/// The state of a replayed game after one laser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayStep {
+    pub shift: u8,
+    pub direction: Direction,
+    /// What the laser showed where it entered the grid.
+    pub observation: Observation,
+    /// Everything observed up to and including this laser.
+    pub observations: Observations,
+}
+
+impl GameSession {
+    /// Starts the clock for a new game.
+    pub fn new(game: Game) -> Self {
+        Self::resume(game, Duration::ZERO)
+    }
+
+    /// Continues a game which was played for the given time already.
+    pub fn resume(game: Game, elapsed: Duration) -> Self {
+        let resumed = (!game.is_finished()).then(Instant::now);
+        GameSession {
+            game,
+            earlier: elapsed,
+            resumed,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Gives access to the game. Submit through [GameSession::submit] or [GameSession::run], so
+    /// the clock stops.
+    pub fn game_mut(&mut self) -> &mut Game {
+        &mut self.game
+    }
+
+    /// Ends the game like [Game::submit] and stops the clock.
+    pub fn submit(&mut self) -> GuessResult {
+        let result = self.game.submit();
+        self.stop_clock();
+        result
+    }
+
+    /// Plays the game on the terminal like [game::run]. The clock stops once the player guesses.
+    pub fn run(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        let result = game::run(&mut self.game, input, output);
+        if self.game.is_finished() {
+            self.stop_clock();
+        }
+        result
+    }
+
+    fn stop_clock(&mut self) {
+        self.earlier = self.elapsed();
+        self.resumed = None;
+    }
+
+    /// Time played in all sittings together.
+    pub fn elapsed(&self) -> Duration {
+        self.earlier + self.resumed.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+
+    /// Fires the lasers of the game again in the same order.
+    pub fn replay(&self) -> Vec<ReplayStep> {
+        let mut game = Game::new(self.game.hidden().clone(), self.game.observations().rules());
+        self.game
+            .probes()
+            .iter()
+            .map(|&(shift, direction)| {
+                let observation = game
+                    .fire(shift, direction)
+                    .expect("Lasers are only recorded once");
+                ReplayStep {
+                    shift,
+                    direction,
+                    observation,
+                    observations: game.observations().clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the session to a file in the text format described in the [module](self).
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Reads a session written by [GameSession::save] and starts the clock again.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Writes the text format described in the [module](self).
+impl Display for GameSession {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# laser-puzzle game")?;
+        writeln!(f, "rules {}", self.game.observations().rules())?;
+        writeln!(f, "time {}", self.elapsed().as_secs())?;
+        for (shift, direction) in self.game.undone() {
+            writeln!(f, "fire {} {}\nundo", game::side_name(direction), shift)?;
+        }
+        for (shift, direction) in self.game.probes() {
+            writeln!(f, "fire {} {}", game::side_name(direction), shift)?;
+        }
+        let marks = self.game.marks();
+        for v in marks.size().cells().filter(|&v| marks.get(v)) {
+            writeln!(f, "mark {} {}", v.x, v.y)?;
+        }
+        if self.game.is_finished() {
+            writeln!(f, "guess")?;
+        }
+        writeln!(f, "hidden")?;
+        write!(f, "{}", self.game.hidden())
+    }
+}
+
+/// Reads the text format described in the [module](self). The moves are played again, so the
+/// lasers show the same as before.
+impl FromStr for GameSession {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        let mut rules = Rules::CLASSIC;
+        let mut elapsed = Duration::ZERO;
+        let mut moves = vec![];
+        loop {
+            let Some((number, line)) = lines.next() else {
+                return Err("The hidden grid is missing".to_string());
+            };
+            let error = |message: String| format!("Line {}: {}", number, message);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "hidden" {
+                break;
+            }
+            match line.split_once(' ') {
+                Some(("rules", value)) => rules = value.trim().parse().map_err(error)?,
+                Some(("time", value)) => {
+                    let seconds = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| error(format!("\"{}\" is not a valid time", value)))?;
+                    elapsed = Duration::from_secs(seconds);
+                }
+                _ => match game::parse_command(line).map_err(error)? {
+                    command @ (Command::Fire(..)
+                    | Command::Undo
+                    | Command::Mark(_)
+                    | Command::Guess) => moves.push((number, command)),
+                    _ => return Err(error(format!("Unexpected command \"{}\"", line))),
+                },
+            }
+        }
+        let hidden: AtomGrid = lines
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .parse()
+            .map_err(|e| format!("Hidden grid: {}", e))?;
+
+        let size = hidden.size();
+        let mut game = Game::new(hidden, rules);
+        for (number, command) in moves {
+            let error = |message: &str| Err(format!("Line {}: {}", number, message));
+            if game.is_finished() {
+                return error("The game is over already");
+            }
+            match command {
+                Command::Fire(shift, direction) => {
+                    if shift >= size.side_length(direction) {
+                        return error("There is no such laser");
+                    }
+                    if game.fire(shift, direction).is_none() {
+                        return error("The result of this laser is known already");
+                    }
+                }
+                Command::Undo => {
+                    if game.undo().is_none() {
+                        return error("There is no laser to take back");
+                    }
+                }
+                Command::Mark(v) => {
+                    if !v.in_grid(size) {
+                        return error("There is no such field");
+                    }
+                    game.toggle_mark(v);
+                }
+                Command::Guess => {
+                    game.submit();
+                }
+                _ => unreachable!("Only moves are collected"),
+            }
+        }
+        Ok(GameSession::resume(game, elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::DEFAULT_SIZE;
+    use crate::i8vec2::I8Vec2;
+    use crate::laser::Direction::{Down, Right, Up};
+    use crate::observation::{LASER_ABSORBED, NOT_PROBED};
+
+    fn started_game() -> Game {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let mut game = Game::new(grid, Rules::CLASSIC);
+        game.fire(2, Right);
+        game.fire(5, Down);
+        game.fire(0, Right);
+        game.fire(3, Up);
+        game.undo();
+        game.toggle_mark(I8Vec2::new(3, 4));
+        game
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let session = GameSession::resume(started_game(), Duration::from_secs(95));
+        let text = session.to_string();
+        assert!(text.contains(
+            "time 95\nfire bottom 3\nundo\nfire left 2\nfire top 5\nfire left 0\nmark 3 4\nhidden\n"
+        ));
+
+        let loaded: GameSession = text.parse().unwrap();
+        let (game, original) = (loaded.game(), session.game());
+        assert_eq!(game.observations(), original.observations());
+        assert_eq!(game.probes(), original.probes());
+        assert_eq!(game.undone(), original.undone());
+        assert_eq!(game.marks(), original.marks());
+        assert_eq!(game.hidden(), original.hidden());
+        assert!(!game.is_finished());
+        assert!(loaded.elapsed() >= Duration::from_secs(95));
+
+        // The clock stops once the game is over.
+        let mut finished = loaded;
+        finished.submit();
+        let elapsed = finished.elapsed();
+        assert!(elapsed >= Duration::from_secs(95));
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(finished.elapsed(), elapsed);
+        let loaded: GameSession = finished.to_string().parse().unwrap();
+        assert!(loaded.game().is_finished());
+        assert_eq!(loaded.elapsed(), Duration::from_secs(95));
+        assert_eq!(loaded.game().score(), finished.game().score());
+    }
+
+    #[test]
+    fn test_save_to_file() {
+        let path = std::env::temp_dir().join(format!("laser-puzzle-{}.txt", std::process::id()));
+        let session = GameSession::new(started_game());
+        session.save(&path).unwrap();
+        let loaded = GameSession::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.game().probes(), session.game().probes());
+        assert!(GameSession::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_invalid_sessions() {
+        let text = GameSession::new(started_game()).to_string();
+        let parse = |text: &str| text.parse::<GameSession>().map(|_| ());
+        assert!(parse(&text).is_ok());
+
+        assert_eq!(
+            parse(&text.replace("fire left 0", "fire left 2")),
+            Err("Line 8: The result of this laser is known already".to_string())
+        );
+        assert_eq!(
+            parse(&text.replace("fire bottom 3\n", "")),
+            Err("Line 4: There is no laser to take back".to_string())
+        );
+        assert!(parse(&text.replace("mark 3 4", "mark 3 4\nguess\nundo")).is_err());
+        assert!(parse(&text.replace("mark 3 4", "mark 3 4\nguess\nfire top 0")).is_err());
+        assert!(parse(&text.replace("mark 3 4", "hint")).is_err());
+        assert!(parse(&text.replace("rules classic", "rules quantum")).is_err());
+        assert!(parse(&text.replace("hidden\n", "")).is_err());
+        assert!(parse(&text.replace(" . ", " x ")).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let session = GameSession::new(started_game());
+        let steps = session.replay();
+        assert_eq!(steps.len(), 3);
+        assert_eq!((steps[0].shift, steps[0].direction), (2, Right));
+        assert_eq!(steps[0].observation, LASER_ABSORBED);
+        let probed = |observations: &Observations| {
+            observations
+                .iter()
+                .into_iter()
+                .filter(|&(_, _, obs)| obs != NOT_PROBED)
+                .count()
+        };
+        assert_eq!(probed(&steps[0].observations), 1);
+        assert_eq!(&steps[2].observations, session.game().observations());
+    }
+}
@@ -1,62 +1,192 @@
 //! A solver that takes observations and derives information about the atom grid.
+//!
+//! The solver is a list of deduction [Rule]s which are applied over and over again until none of
+//! them can derive anything new. Rules may build on the knowledge found by other rules, so their
+//! order does not matter for the final result.
 
-use crate::atom_grid::GRID_SIZE;
+use crate::atom_grid::GridSize;
 use crate::i8vec2::I8Vec2;
 use crate::laser::Direction::{Down, Left, Right, Up};
-use crate::laser::LaserTip;
-use crate::observation::{Observations, LASER_ABSORBED, LASER_REFLECTED};
+use crate::laser::{Direction, LaserTip, Rules};
+use crate::observation::{Observations, LASER_ABSORBED, LASER_REFLECTED, NOT_PROBED};
 use crate::solver::GridKnowledge::{Empty, Unknown};
-use std::fmt::Write;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
 use GridKnowledge::Atom;
 
-#[derive(Default)]
+/// What is known about each field of the grid.
+///
+/// With the `serde` feature, the knowledge is stored as a 2D array, row by row. Where it came from
+/// is not stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "Vec<Vec<GridKnowledge>>", try_from = "Vec<Vec<GridKnowledge>>")
+)]
 pub struct UncertainGrid {
-    atoms: [[GridKnowledge; GRID_SIZE]; GRID_SIZE],
+    size: GridSize,
+    atoms: Vec<GridKnowledge>,
+    /// Where the knowledge about each field came from.
+    sources: Vec<Option<Source>>,
 }
 
 impl UncertainGrid {
-    fn get(&self, v: I8Vec2) -> GridKnowledge {
-        self.atoms[v.x as usize][v.y as usize]
+    /// Creates a grid where nothing is known yet.
+    pub fn new(size: GridSize) -> Self {
+        Self {
+            size,
+            atoms: vec![Unknown; size.cell_count()],
+            sources: vec![None; size.cell_count()],
+        }
+    }
+
+    pub fn size(&self) -> GridSize {
+        self.size
+    }
+
+    /// Positions outside the grid are known to be empty.
+    pub fn get(&self, v: I8Vec2) -> GridKnowledge {
+        if v.in_grid(self.size) {
+            self.atoms[self.size.index(v)]
+        } else {
+            Empty
+        }
     }
 
-    /// Sets a value, but does nothing if the given vector is outside the grid.
-    fn set_safe(&mut self, v: I8Vec2, knowledge: GridKnowledge) {
+    /// Sets a value, but does nothing if the given vector is outside the grid. Returns a
+    /// [Contradiction] if the opposite is known already.
+    pub fn set_safe(
+        &mut self,
+        v: I8Vec2,
+        knowledge: GridKnowledge,
+        source: Source,
+    ) -> Result<(), Contradiction> {
         if knowledge == Unknown {
             panic!("Can not update with Unknown at {:?}", v);
         }
-        if v.in_grid() {
-            // Check consistency and crash when updating with inconsistent information.
-            let previous_knowledge = self.atoms[v.x as usize][v.y as usize];
-            if previous_knowledge != Unknown && previous_knowledge != knowledge {
-                panic!(
-                    "Updating existing knowledge {:?} with inconsistent {:?} at {:?}",
-                    previous_knowledge, knowledge, v
-                );
+        if v.in_grid(self.size) {
+            // Check consistency and refuse updating with inconsistent information.
+            let index = self.size.index(v);
+            let previous_knowledge = self.atoms[index];
+            if previous_knowledge == Unknown {
+                self.atoms[index] = knowledge;
+                self.sources[index] = Some(source);
+            } else if previous_knowledge != knowledge {
+                return Err(Contradiction {
+                    position: v,
+                    previous_knowledge,
+                    previous_source: self.sources[index],
+                    knowledge,
+                    source,
+                });
             }
-            self.atoms[v.x as usize][v.y as usize] = knowledge;
+        }
+        Ok(())
+    }
+}
+
+/// The knowledge about the grid, row by row.
+impl From<UncertainGrid> for Vec<Vec<GridKnowledge>> {
+    fn from(grid: UncertainGrid) -> Self {
+        grid.atoms
+            .chunks(grid.size.width() as usize)
+            .map(<[GridKnowledge]>::to_vec)
+            .collect()
+    }
+}
+
+/// Builds a grid from the knowledge about it, row by row, without any sources.
+impl TryFrom<Vec<Vec<GridKnowledge>>> for UncertainGrid {
+    type Error = String;
+
+    fn try_from(rows: Vec<Vec<GridKnowledge>>) -> Result<Self, String> {
+        let size = GridSize::of_rows(&rows)?;
+        Ok(Self {
+            size,
+            atoms: rows.concat(),
+            sources: vec![None; size.cell_count()],
+        })
+    }
+}
+
+/// The rule and the observation some knowledge was derived from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    pub rule: &'static str,
+    /// The observed laser, as passed to [LaserTip::new].
+    pub direction: Direction,
+    pub shift: u8,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let side = match self.direction {
+            Up => "bottom",
+            Down => "top",
+            Left => "right",
+            Right => "left",
+        };
+        write!(
+            f,
+            "\"{}\" applied to laser {} on the {} side",
+            self.rule, self.shift, side
+        )
+    }
+}
+
+/// Two pieces of derived knowledge disagree about a field, so the observations can not come from a
+/// real atom grid. Each source names the last rule and observation that led to the knowledge, the
+/// rule may have built on knowledge derived from other observations before.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contradiction {
+    pub position: I8Vec2,
+    pub previous_knowledge: GridKnowledge,
+    /// Only missing if the knowledge was put in without a solver rule.
+    pub previous_source: Option<Source>,
+    pub knowledge: GridKnowledge,
+    pub source: Source,
+}
+
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Field ({}, {}) is {:?} by {}",
+            self.position.x, self.position.y, self.knowledge, self.source
+        )?;
+        match self.previous_source {
+            Some(previous_source) => write!(
+                f,
+                ", but {:?} by {}",
+                self.previous_knowledge, previous_source
+            ),
+            None => write!(f, ", but known to be {:?}", self.previous_knowledge),
         }
     }
 }
 
+impl Error for Contradiction {}
+
 pub fn draw(grid: &UncertainGrid, observations: &Observations) -> Result<String, std::fmt::Error> {
     let mut f = String::new();
     // first, display the row above with lasers pointing down
     f.write_str("  ")?;
-    for obs in observations.sides[Down as usize] {
+    for obs in &observations.sides[Down as usize] {
         f.write_str(&format!(" {}", obs))?;
     }
     f.write_char('\n')?;
 
-    let left_border = observations.sides[Right as usize];
-    let right_border = observations.sides[Left as usize];
+    let left_border = &observations.sides[Right as usize];
+    let right_border = &observations.sides[Left as usize];
 
     // Show rows
-    for y in 0..GRID_SIZE {
+    for y in 0..grid.size.height() as usize {
         let left_obs = left_border[y];
         let right_obs = right_border[y];
 
         f.write_str(&format!(" {}", left_obs))?;
-        for x in 0..GRID_SIZE {
+        for x in 0..grid.size.width() as usize {
             match grid.get(I8Vec2::new(x as i8, y as i8)) {
                 Unknown => f.write_str(" ?")?,
                 Atom => f.write_str(" o")?,
@@ -67,7 +197,7 @@ pub fn draw(grid: &UncertainGrid, observations: &Observations) -> Result<String,
     }
 
     f.write_str("  ")?;
-    for obs in observations.sides[Up as usize] {
+    for obs in &observations.sides[Up as usize] {
         f.write_str(&format!(" {}", obs))?;
     }
     f.write_char('\n')?;
@@ -75,67 +205,761 @@ pub fn draw(grid: &UncertainGrid, observations: &Observations) -> Result<String,
     Ok(f)
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum GridKnowledge {
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GridKnowledge {
+    #[default]
     Unknown,
     Atom,
     Empty,
 }
 
-impl Default for GridKnowledge {
+/// A single deduction rule. Each application looks at the observations and the knowledge found so
+/// far and writes down everything it can derive from them. Border positions that were not probed
+/// tell nothing, rules must skip them.
+pub trait Rule {
+    /// Short human-readable name of the rule, used for reporting.
+    fn name(&self) -> &'static str;
+
+    /// Whether the rule only derives true facts for lasers following the given rules. Unless
+    /// overridden, only the classic rules are supported.
+    fn supports(&self, rules: &Rules) -> bool {
+        *rules == Rules::CLASSIC
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction>;
+}
+
+/// Applies a list of rules until the knowledge about the grid does not change anymore.
+pub struct Solver {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Default for Solver {
+    /// A solver using all rules known to this crate.
     fn default() -> Self {
-        Unknown
+        Solver::empty()
+            .with_rule(LetterFindsFourEmptySpaces)
+            .with_rule(ReflectionIsNotBlocked)
+            .with_rule(AbsorptionWithOneFreeField)
+            .with_rule(AbsorptionInFreeLaneFreesRow)
+            .with_rule(ReflectionInFreeLaneNeedsBorderAtom)
+            .with_rule(AbsorptionNeedsAtomInThreeRows)
+            .with_rule(PassingLaserIsNeverBlocked)
     }
 }
 
-pub fn solve_as_much_as_you_can(observations: &Observations) -> UncertainGrid {
-    let mut grid = UncertainGrid::default();
+impl Solver {
+    /// A solver without any rules. Add rules with [Solver::with_rule].
+    pub fn empty() -> Self {
+        Solver { rules: vec![] }
+    }
 
-    letter_finds_four_empty_spaces(&mut grid, observations);
-    reflection_is_not_blocked(&mut grid, observations);
+    pub fn with_rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
 
-    // Benefits from "Free field" information.
-    absorption_with_one_free_field(&mut grid, observations);
+    /// Derives as much as possible. Any number of lasers may have been fired, fields no laser tells
+    /// anything about stay unknown. Fails if the observations contradict each other, which can
+    /// only happen if they were not produced by a real atom grid.
+    pub fn solve(&self, observations: &Observations) -> Result<UncertainGrid, Contradiction> {
+        Ok(self.solve_with_statistics(observations)?.0)
+    }
+
+    /// Like [Solver::solve], but also reports how the result was found.
+    pub fn solve_with_statistics(
+        &self,
+        observations: &Observations,
+    ) -> Result<(UncertainGrid, Statistics), Contradiction> {
+        let mut grid = UncertainGrid::new(observations.size());
+        let mut statistics = Statistics::default();
+        loop {
+            let previous = grid.clone();
+            for rule in &self.rules {
+                if !rule.supports(&observations.rules()) {
+                    continue;
+                }
+                let before = grid.clone();
+                rule.apply(&mut grid, observations)?;
+                if grid != before && !statistics.rules_used.contains(&rule.name()) {
+                    statistics.rules_used.push(rule.name());
+                }
+            }
+            if grid == previous {
+                return Ok((grid, statistics));
+            }
+            statistics.rounds += 1;
+        }
+    }
+}
 
-    grid
+/// How the solver reached its result.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Number of times all rules were applied and derived something new. The final round which
+    /// only confirmed that nothing changes anymore is not counted.
+    pub rounds: usize,
+    /// Names of the rules which derived anything, in the order they first did so.
+    pub rules_used: Vec<&'static str>,
 }
 
-fn reflection_is_not_blocked(grid: &mut UncertainGrid, observations: &Observations) {
-    for (direction, shift, obs) in observations.iter() {
-        if obs == LASER_REFLECTED {
-            let l = LaserTip::new(shift as u8, direction);
-            let center = l.forward().position();
+/// Solves using the [default](Solver::default) rule set.
+pub fn solve_as_much_as_you_can(
+    observations: &Observations,
+) -> Result<UncertainGrid, Contradiction> {
+    Solver::default().solve(observations)
+}
 
-            grid.set_safe(center, Empty);
+/// A reflected laser could enter the grid, so the first field is empty.
+pub struct ReflectionIsNotBlocked;
+
+impl Rule for ReflectionIsNotBlocked {
+    fn name(&self) -> &'static str {
+        "reflection is not blocked"
+    }
+
+    fn supports(&self, rules: &Rules) -> bool {
+        // Without absorption, an atom in front reflects.
+        rules.absorption
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == LASER_REFLECTED {
+                let l = LaserTip::new(shift, direction, observations.size());
+                let center = l.forward().position();
+
+                grid.set_safe(center, Empty, source)?;
+            }
         }
+        Ok(())
     }
 }
 
-fn absorption_with_one_free_field(grid: &mut UncertainGrid, observations: &Observations) {
-    for (direction, shift, obs) in observations.iter() {
-        if obs == LASER_ABSORBED {
-            let l = LaserTip::new(shift as u8, direction);
-            let center = l.forward().position();
+/// An absorbed laser with a free first field was not reflected at the edge, so the fields to the
+/// left and right of the first field are empty as well.
+pub struct AbsorptionWithOneFreeField;
+
+impl Rule for AbsorptionWithOneFreeField {
+    fn name(&self) -> &'static str {
+        "absorption with one free field"
+    }
+
+    fn supports(&self, rules: &Rules) -> bool {
+        rules.edge_reflection
+    }
 
-            if grid.get(center) == Empty {
-                grid.set_safe(center + direction.clockwise().dxy(), Empty);
-                grid.set_safe(center + direction.counter_clockwise().dxy(), Empty);
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == LASER_ABSORBED {
+                let l = LaserTip::new(shift, direction, observations.size());
+                let center = l.forward().position();
+
+                if grid.get(center) == Empty {
+                    grid.set_safe(center + direction.clockwise().dxy(), Empty, source)?;
+                    grid.set_safe(center + direction.counter_clockwise().dxy(), Empty, source)?;
+                }
             }
         }
+        Ok(())
     }
 }
 
-fn letter_finds_four_empty_spaces(grid: &mut UncertainGrid, observations: &Observations) {
-    for (direction, shift, obs) in observations.iter() {
-        if obs.is_letter() {
-            let l = LaserTip::new(shift as u8, direction);
-            let center = l.forward().position();
+/// A laser that comes out somewhere else passed its first field without being absorbed, reflected
+/// or deflected, so the first field and its four neighbours are empty.
+pub struct LetterFindsFourEmptySpaces;
+
+impl Rule for LetterFindsFourEmptySpaces {
+    fn name(&self) -> &'static str {
+        "letter finds four empty spaces"
+    }
+
+    fn supports(&self, rules: &Rules) -> bool {
+        // Without edge reflection, atoms next to the first field are ignored.
+        rules.edge_reflection
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs.is_letter() {
+                let l = LaserTip::new(shift, direction, observations.size());
+                let center = l.forward().position();
+
+                grid.set_safe(center, Empty, source)?;
+                grid.set_safe(center + I8Vec2::new(0, 1), Empty, source)?;
+                grid.set_safe(center + I8Vec2::new(0, -1), Empty, source)?;
+                grid.set_safe(center + I8Vec2::new(1, 0), Empty, source)?;
+                grid.set_safe(center + I8Vec2::new(-1, 0), Empty, source)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields a laser entering at the given border position crosses in a straight line before
+/// reaching the first field which is not known to be empty.
+fn free_lane(grid: &UncertainGrid, entry: LaserTip) -> Vec<I8Vec2> {
+    let mut lane = vec![];
+    let mut v = entry.forward().position();
+    while v.in_grid(grid.size) && grid.get(v) == Empty {
+        lane.push(v);
+        v = v + entry.direction().dxy();
+    }
+    lane
+}
+
+/// Checks that everything from the lane towards `side` up to the border of the grid is empty.
+fn side_is_free(grid: &UncertainGrid, lane: &[I8Vec2], side: Direction) -> bool {
+    lane.iter().all(|&start| {
+        let mut v = start + side.dxy();
+        while v.in_grid(grid.size) {
+            if grid.get(v) != Empty {
+                return false;
+            }
+            v = v + side.dxy();
+        }
+        true
+    })
+}
+
+/// An absorbed laser running along a free lane with nothing but free fields on one side up to the
+/// border can not be deflected to that side, it would leave the grid there. So the row on the other
+/// side of the lane is free as well (marked with +).
+/// ```text
+///   + + + + ?
+/// × . . . . ?
+///   . . . . ?
+///   . . . . ?
+/// ```
+pub struct AbsorptionInFreeLaneFreesRow;
+
+impl Rule for AbsorptionInFreeLaneFreesRow {
+    fn name(&self) -> &'static str {
+        "absorption in free lane frees row"
+    }
+
+    fn supports(&self, rules: &Rules) -> bool {
+        rules.edge_reflection
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == LASER_ABSORBED {
+                let lane = free_lane(grid, LaserTip::new(shift, direction, observations.size()));
+                for side in [direction.clockwise(), direction.counter_clockwise()] {
+                    if side_is_free(grid, &lane, side) {
+                        for &v in &lane {
+                            grid.set_safe(v + side.flip().dxy(), Empty, source)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reflected laser running along a free lane into an atom, with nothing but free fields on one
+/// side up to the border, can neither be absorbed nor deflected to that side. The only way back is
+/// an atom at the border, diagonal to the entry.
+/// ```text
+///   o ? ? ? ?
+/// ⇄ . . . o ?
+///   . . . . ?
+///   . . . . ?
+/// ```
+pub struct ReflectionInFreeLaneNeedsBorderAtom;
+
+impl Rule for ReflectionInFreeLaneNeedsBorderAtom {
+    fn name(&self) -> &'static str {
+        "reflection in free lane needs border atom"
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == LASER_REFLECTED {
+                let lane = free_lane(grid, LaserTip::new(shift, direction, observations.size()));
+                let Some(&last) = lane.last() else {
+                    continue;
+                };
+                let end = last + direction.dxy();
+                if !end.in_grid(grid.size) || grid.get(end) != Atom {
+                    continue;
+                }
+                for side in [direction.clockwise(), direction.counter_clockwise()] {
+                    if side_is_free(grid, &lane, side) {
+                        grid.set_safe(lane[0] + side.flip().dxy(), Atom, source)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An absorbed laser has to meet an atom before it crosses the grid. The first atom it meets is in
+/// its own row or one of the two rows next to it. If all but one field of these three rows are
+/// known to be empty, the remaining one is an atom.
+pub struct AbsorptionNeedsAtomInThreeRows;
+
+impl Rule for AbsorptionNeedsAtomInThreeRows {
+    fn name(&self) -> &'static str {
+        "absorption needs atom in three rows"
+    }
+
+    fn supports(&self, _rules: &Rules) -> bool {
+        // Without absorption, there is nothing to derive from.
+        true
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == LASER_ABSORBED {
+                let first = LaserTip::new(shift, direction, observations.size())
+                    .forward()
+                    .position();
+                let side = direction.clockwise().dxy();
+
+                let mut has_atom = false;
+                let mut unknown = vec![];
+                let mut v = first;
+                while v.in_grid(grid.size) {
+                    for w in [v - side, v, v + side] {
+                        match grid.get(w) {
+                            Atom => has_atom = true,
+                            Unknown => unknown.push(w),
+                            Empty => {}
+                        }
+                    }
+                    v = v + direction.dxy();
+                }
+
+                if let (false, [w]) = (has_atom, &unknown[..]) {
+                    grid.set_safe(*w, Atom, source)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A laser that is not absorbed never has an atom right in front of it. We follow its path as far
+/// as the known fields allow and free every field it heads into.
+pub struct PassingLaserIsNeverBlocked;
+
+impl Rule for PassingLaserIsNeverBlocked {
+    fn name(&self) -> &'static str {
+        "passing laser is never blocked"
+    }
+
+    fn apply(
+        &self,
+        grid: &mut UncertainGrid,
+        observations: &Observations,
+    ) -> Result<(), Contradiction> {
+        for (direction, shift, obs) in observations.iter() {
+            let source = Source {
+                rule: self.name(),
+                direction,
+                shift,
+            };
+            if obs == NOT_PROBED || obs == LASER_ABSORBED {
+                continue;
+            }
+            let mut laser = LaserTip::new(shift, direction, observations.size());
+            // A path without loops visits each field at most once per direction.
+            for _ in 0..4 * grid.size.cell_count() {
+                let front = laser.forward().position();
+                grid.set_safe(front, Empty, source)?;
+
+                let corners = [
+                    front + laser.direction().clockwise().dxy(),
+                    front + laser.direction().counter_clockwise().dxy(),
+                ];
+                if corners.iter().any(|&v| grid.get(v) == Unknown) {
+                    break;
+                }
+                match laser.move_with(&observations.rules(), |v| grid.get(v) == Atom) {
+                    Some(l) if l.position().in_grid(grid.size) => laser = l,
+                    _ => break,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom_grid::{AtomGrid, GridSize, DEFAULT_SIZE};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Writes down a fixed cell, to check that the solver keeps iterating on new knowledge.
+    struct Marker(I8Vec2);
+
+    impl Rule for Marker {
+        fn name(&self) -> &'static str {
+            "marker"
+        }
+
+        fn apply(
+            &self,
+            grid: &mut UncertainGrid,
+            _observations: &Observations,
+        ) -> Result<(), Contradiction> {
+            let source = Source {
+                rule: self.name(),
+                direction: Right,
+                shift: self.0.y as u8,
+            };
+            grid.set_safe(self.0, Empty, source)
+        }
+    }
+
+    #[test]
+    fn test_rules_reach_fixpoint() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 35184640598018);
+        let observations = Observations::observe_all(&grid);
+        println!(
+            "{}",
+            draw(
+                &solve_as_much_as_you_can(&observations).unwrap(),
+                &observations
+            )
+            .unwrap()
+        );
+
+        // The top left corner is absorbed. Once a later rule marks its first field as empty, the
+        // absorption rule must run again to free the fields next to it.
+        assert_eq!(observations.sides[Right as usize][2], LASER_ABSORBED);
+        let center = I8Vec2::new(0, 2);
+        let solver = Solver::empty()
+            .with_rule(AbsorptionWithOneFreeField)
+            .with_rule(Marker(center));
+        let knowledge = solver.solve(&observations).unwrap();
+        assert_eq!(knowledge.get(center), Empty);
+        assert_eq!(knowledge.get(I8Vec2::new(0, 1)), Empty);
+        assert_eq!(knowledge.get(I8Vec2::new(0, 3)), Empty);
+        let (_, statistics) = solver.solve_with_statistics(&observations).unwrap();
+        assert_eq!(statistics.rounds, 2);
+        assert_eq!(
+            statistics.rules_used,
+            ["marker", "absorption with one free field"]
+        );
+
+        // The full solver never contradicts the real grid.
+        let knowledge = solve_as_much_as_you_can(&observations).unwrap();
+        for v in DEFAULT_SIZE.cells() {
+            match knowledge.get(v) {
+                Unknown => {}
+                Atom => assert!(grid.get(v)),
+                Empty => assert!(!grid.get(v)),
+            }
+        }
+    }
+
+    /// Knowledge of exactly the given cells, taken from the real grid.
+    fn reveal(grid: &AtomGrid, cells: impl IntoIterator<Item = (i8, i8)>) -> UncertainGrid {
+        let mut knowledge = UncertainGrid::new(grid.size());
+        for (x, y) in cells {
+            let index = grid.size().index(I8Vec2::new(x, y));
+            knowledge.atoms[index] = if grid.get(I8Vec2::new(x, y)) {
+                Atom
+            } else {
+                Empty
+            };
+        }
+        knowledge
+    }
+
+    fn assert_knowledge(
+        knowledge: &UncertainGrid,
+        cells: impl IntoIterator<Item = (i8, i8)>,
+        expected: GridKnowledge,
+    ) {
+        for (x, y) in cells {
+            assert_eq!(
+                knowledge.get(I8Vec2::new(x, y)),
+                expected,
+                "at ({}, {})",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn test_absorption_in_free_lane_frees_row() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 4612249002740547584);
+        println!("{}", grid);
+        let observations = Observations::observe_all(&grid);
+        assert_eq!(observations.sides[Right as usize][3], LASER_ABSORBED);
+
+        // The lane in front of the atom in row 3 and everything below it is free.
+        let lane = (0..4).map(|x| (x, 3));
+        let below = (4..8).flat_map(|y| (0..4).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, lane.chain(below));
+        AbsorptionInFreeLaneFreesRow
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+
+        assert_knowledge(&knowledge, (0..4).map(|x| (x, 2)), Empty);
+        assert_knowledge(&knowledge, [(4, 2), (4, 3)], Unknown);
+    }
+
+    #[test]
+    fn test_reflection_in_free_lane_needs_border_atom() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 140771848094208);
+        println!("{}", grid);
+        let observations = Observations::observe_all(&grid);
+        assert_eq!(observations.sides[Right as usize][3], LASER_REFLECTED);
+
+        let lane = (0..5).map(|x| (x, 3));
+        let below = (4..8).flat_map(|y| (0..4).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, lane.chain(below));
+        ReflectionInFreeLaneNeedsBorderAtom
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+        assert_knowledge(&knowledge, [(0, 2)], Atom);
+
+        // Without the atom ending the lane, the laser might also come back from further away.
+        let lane = (0..4).map(|x| (x, 3));
+        let below = (4..8).flat_map(|y| (0..4).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, lane.chain(below));
+        ReflectionInFreeLaneNeedsBorderAtom
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+        assert_knowledge(&knowledge, [(0, 2)], Unknown);
+    }
+
+    #[test]
+    fn test_absorption_needs_atom_in_three_rows() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 2305843026393563137);
+        println!("{}", grid);
+        let observations = Observations::observe_all(&grid);
+        assert_eq!(observations.sides[Right as usize][3], LASER_ABSORBED);
+
+        let band = (2..5).flat_map(|y| (0..8).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, band.filter(|&v| v != (5, 3)));
+        AbsorptionNeedsAtomInThreeRows
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+        assert_knowledge(&knowledge, [(5, 3)], Atom);
+
+        // Two candidates are left, so nothing can be derived.
+        let band = (2..5).flat_map(|y| (0..8).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, band.filter(|&v| v != (5, 3) && v != (0, 2)));
+        AbsorptionNeedsAtomInThreeRows
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+        assert_knowledge(&knowledge, [(5, 3), (0, 2)], Unknown);
+    }
+
+    #[test]
+    fn test_passing_laser_is_never_blocked() {
+        let grid = AtomGrid::from_bitboard(DEFAULT_SIZE, 4398046527488);
+        println!("{}", grid);
+        let observations = Observations::observe_all(&grid);
+        assert!(observations.sides[Right as usize][1].is_letter());
+
+        // Knowing the rows next to the path, we can follow the laser until it is deflected upwards.
+        let rows = [0, 2].into_iter().flat_map(|y| (0..8).map(move |x| (x, y)));
+        let mut knowledge = reveal(&grid, rows);
+        PassingLaserIsNeverBlocked
+            .apply(&mut knowledge, &observations)
+            .unwrap();
+        assert_knowledge(&knowledge, (0..6).map(|x| (x, 1)), Empty);
+    }
+
+    /// No rule may ever derive something that contradicts the grid the observations came from.
+    #[test]
+    fn test_rules_are_sound() {
+        assert_rules_sound(0, |grid, rules, _| {
+            Observations::observe_all_with_rules(grid, rules)
+        });
+    }
+
+    /// Players usually fire only a few lasers. Unprobed border positions carry no information, so
+    /// nothing may be derived from them.
+    #[test]
+    fn test_rules_are_sound_for_sparse_observations() {
+        assert_rules_sound(1, sparse_observations);
+    }
+
+    /// Solves the observations of many random grids and checks everything derived against the
+    /// grid.
+    fn assert_rules_sound(
+        seed: u64,
+        make_observations: impl Fn(&AtomGrid, Rules, &mut StdRng) -> Observations,
+    ) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let variants = ["classic", "no-edge-reflection", "no-absorption"];
+        for rules in variants.into_iter().map(|r| r.parse::<Rules>().unwrap()) {
+            for size in [DEFAULT_SIZE, GridSize::square(5), GridSize::new(9, 6)] {
+                for atom_count in 1..=6 {
+                    for _ in 0..50 {
+                        let grid = AtomGrid::random(size, atom_count, &mut rng);
+                        let observations = make_observations(&grid, rules, &mut rng);
+                        let knowledge = solve_as_much_as_you_can(&observations).unwrap();
+                        for v in size.cells() {
+                            match knowledge.get(v) {
+                                Unknown => {}
+                                Atom => assert!(grid.get(v), "{:?}\n{}", rules, grid),
+                                Empty => assert!(!grid.get(v), "{:?}\n{}", rules, grid),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_observations() {
+        let size = GridSize::square(5);
+        let knowledge = solve_as_much_as_you_can(&Observations::new(size)).unwrap();
+        assert!(size.cells().all(|v| knowledge.get(v) == Unknown));
+
+        // A single laser passing straight through the empty grid only frees the fields next to
+        // its ends, everything else stays unknown.
+        let grid = AtomGrid::new(size);
+        let mut observations = Observations::new(size);
+        observations.probe(LaserTip::new(2, Right, size), &grid);
+        let knowledge = solve_as_much_as_you_can(&observations).unwrap();
+        let empty: Vec<I8Vec2> = size
+            .cells()
+            .filter(|&v| knowledge.get(v) == Empty)
+            .collect();
+        let expected = [
+            (0, 1),
+            (4, 1),
+            (0, 2),
+            (1, 2),
+            (3, 2),
+            (4, 2),
+            (0, 3),
+            (4, 3),
+        ];
+        assert_eq!(empty, expected.map(|(x, y)| I8Vec2::new(x, y)));
+        assert!(size.cells().all(|v| knowledge.get(v) != Atom));
+    }
+
+    /// Fires up to eight random lasers.
+    fn sparse_observations(grid: &AtomGrid, rules: Rules, rng: &mut StdRng) -> Observations {
+        let size = grid.size();
+        let mut observations = Observations::with_rules(size, rules);
+        for _ in 0..rng.gen_range(0..=8) {
+            let direction = Direction::all()[rng.gen_range(0..4)];
+            let shift = rng.gen_range(0..size.side_length(direction));
+            if observations.get(direction, shift) == NOT_PROBED {
+                observations.probe(LaserTip::new(shift, direction, size), grid);
+            }
+        }
+        observations
+    }
+
+    /// A typo in the observations is reported instead of crashing the solver.
+    #[test]
+    fn test_contradiction() {
+        let mut grid = AtomGrid::new(DEFAULT_SIZE);
+        grid.set(I8Vec2::new(3, 3), true);
+        let mut observations = Observations::observe_all(&grid);
+        assert_eq!(observations.sides[Right as usize][3], LASER_ABSORBED);
+        observations.sides[Right as usize][3] = LASER_REFLECTED;
+
+        let contradiction = solve_as_much_as_you_can(&observations).unwrap_err();
+        println!("{}", contradiction);
+        assert_eq!(contradiction.position, I8Vec2::new(3, 1));
+        assert_eq!(contradiction.previous_knowledge, Atom);
+        assert_eq!(
+            contradiction.previous_source,
+            Some(Source {
+                rule: AbsorptionNeedsAtomInThreeRows.name(),
+                direction: Up,
+                shift: 3
+            })
+        );
+        assert_eq!(contradiction.knowledge, Empty);
+        assert_eq!(contradiction.source.rule, PassingLaserIsNeverBlocked.name());
+
+        // Without the typo, there is nothing to complain about.
+        assert!(solve_as_much_as_you_can(&Observations::observe_all(&grid)).is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let json = serde_json::to_string(&UncertainGrid::new(GridSize::new(2, 1))).unwrap();
+        assert_eq!(json, r#"[["Unknown","Unknown"]]"#);
 
-            grid.set_safe(center, Empty);
-            grid.set_safe(center + I8Vec2::new(0, 1), Empty);
-            grid.set_safe(center + I8Vec2::new(0, -1), Empty);
-            grid.set_safe(center + I8Vec2::new(1, 0), Empty);
-            grid.set_safe(center + I8Vec2::new(-1, 0), Empty);
+        let grid = AtomGrid::from_seed(DEFAULT_SIZE, 5, 1);
+        let knowledge = solve_as_much_as_you_can(&Observations::observe_all(&grid)).unwrap();
+        let json = serde_json::to_string(&knowledge).unwrap();
+        let read: UncertainGrid = serde_json::from_str(&json).unwrap();
+        for v in DEFAULT_SIZE.cells() {
+            assert_eq!(read.get(v), knowledge.get(v));
         }
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
     }
 }